@@ -0,0 +1,117 @@
+//! Тестовый собеседник моста
+//!
+//! Заменяет бота Telegram при локальной разработке: выводит
+//! команды, полученные от бота Discord, и пересылает ему
+//! строки стандартного ввода. Строка вида `имя: текст`
+//! отправляется от имени указанного автора.
+//!
+//! Настройки моста читаются из `config.json` бота в текущей
+//! директории. Собеседник слушает адрес, на который бот
+//! отправляет команды, и отправляет команды на адрес бота
+//! тем же транспортом (`unix` или `tcp` с тем же TLS).
+//! Команды подписываются секретом `bridge.auth.secret`,
+//! поэтому подходят и для режима `required`.
+//!
+//! Использование: `cargo run --bin bridge-peer`
+
+use queens_corsar::core::application::build_command_server;
+use queens_corsar::core::auth::BridgeAuth;
+use queens_corsar::core::metrics::Metrics;
+use queens_corsar::core::requests::{BridgeResponse, RequestHandler, SignedRequest};
+use queens_corsar::core::socket;
+use queens_corsar::core::transport::{BridgeSender, TransportKind};
+use queens_corsar::prelude::*;
+use slog::{o, Drain, Logger};
+use std::io::BufRead;
+use std::sync::Arc;
+use std::thread;
+
+const DEFAULT_AUTHOR: &str = "bridge-peer";
+
+/// Обработчик, выводящий полученные команды
+///
+/// Запросы к собеседнику не поддерживаются
+struct PrintingHandler;
+
+impl CommandHandler for PrintingHandler {
+    fn forward_message(&self, msg: Command) -> UResult {
+        println!("<- {:?}", msg);
+        Ok(())
+    }
+}
+
+impl RequestHandler for PrintingHandler {
+    fn handle_request(&self, request: SignedRequest) -> BridgeResponse {
+        BridgeResponse::Unsupported {
+            request: format!("{:?}", request.request),
+        }
+    }
+}
+
+fn configure_logger() -> Logger {
+    let decorator = slog_term::TermDecorator::new().stderr().build();
+    let drain = slog_term::CompactFormat::new(decorator).build().fuse();
+    let drain = slog_async::Async::new(drain).build().fuse();
+    Logger::root(drain, o!())
+}
+
+/// Настройки моста с точки зрения собеседника
+///
+/// Адреса приёма и отправки меняются местами
+fn mirrored(mut config: BridgeConfig) -> BridgeConfig {
+    let transport = &mut config.transport;
+    std::mem::swap(&mut transport.listen_addr, &mut transport.peer_addr);
+    let listen = socket::listen_path(&config.socket);
+    let peer = socket::peer_path(&config.socket);
+    config.socket.listen_path = peer.to_string_lossy().into_owned();
+    config.socket.peer_path = listen.to_string_lossy().into_owned();
+    config
+}
+
+fn command_from_line(line: &str) -> Command {
+    let (name, content) = match line.split_once(':') {
+        Some((name, content)) if !name.trim().is_empty() => (name.trim(), content.trim()),
+        _ => (DEFAULT_AUTHOR, line.trim()),
+    };
+    Command {
+        kind: CommandKind::ForwardMessage {
+            from: ActorInfos { server: "telegram_server_id".to_owned(), name: name.to_owned() },
+            to: ActorInfos { server: "discord_server_id".to_owned(), name: Default::default() },
+            content: content.to_owned(),
+        },
+        sender_bot_family: BotFamily::Telegram,
+        protocol_version: PROTOCOL_VERSION,
+    }
+}
+
+fn main() -> UResult {
+    let logger = configure_logger();
+    let config = mirrored(queens_corsar::config::load_config()?.bridge);
+
+    let server = build_command_server(&config, Arc::new(PrintingHandler), Arc::new(Metrics::default()), &logger)?;
+    {
+        let logger = logger.clone();
+        thread::spawn(move || {
+            if let Err(why) = server.listen() {
+                warn!(logger, "Peer server stopped: {:?}", why);
+            }
+        });
+    }
+    let (listen_addr, target_addr) = match config.transport.kind {
+        TransportKind::Unix => (config.socket.listen_path.clone(), config.socket.peer_path.clone()),
+        TransportKind::Tcp => (config.transport.listen_addr.clone(), config.transport.peer_addr.clone()),
+    };
+    info!(logger, "Listening on {}, forwarding to {}", listen_addr, target_addr);
+
+    let sender = BridgeSender::from_config(&config, Arc::new(BridgeAuth::new(&config.auth)?))?;
+    for line in std::io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if let Err(why) = sender.send(command_from_line(&line)) {
+            warn!(logger, "Could not send the command: {:?}", why);
+        }
+    }
+    Ok(())
+}
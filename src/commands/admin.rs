@@ -0,0 +1,320 @@
+use serenity::{
+    framework::standard::{
+        macros::{check, command, group},
+        Args, CommandOptions, CommandResult, Reason,
+    },
+    model::prelude::*,
+    prelude::*,
+    utils::MessageBuilder,
+};
+
+use crate::core::{
+    approval::{approval_registry, approve_application},
+    bridge::bridge_state,
+    sessions::session_registry,
+    start_signup_session, validate_nickname,
+};
+use crate::prelude::*;
+
+use slog::o;
+
+/// Проверка прав администратора
+///
+/// Пропускает пользователей с правами администратора или
+/// управления сервером, а также обладателей ролей, указанных
+/// в конфигурации (`admin.role_ids`)
+#[check]
+#[name = "Admin"]
+async fn admin_check(
+    ctx: &Context,
+    msg: &Message,
+    _: &mut Args,
+    _: &CommandOptions,
+) -> Result<(), Reason> {
+    let member = msg
+        .member(ctx)
+        .await
+        .map_err(|why| Reason::Log(format!("Could not retrieve the member: {:?}", why)))?;
+
+    match member_is_admin(ctx, &member).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(Reason::User(
+            "Эта команда доступна только администрации гильдии".to_owned(),
+        )),
+        Err(why) => Err(Reason::Log(format!("Could not check the permissions: {:?}", why))),
+    }
+}
+
+async fn admin_logger(ctx: &Context, msg: &Message, command: &str) -> UResult<slog::Logger> {
+    let logger = child_logger(ctx, command).await?;
+    Ok(logger.new(o!(
+        "guild id" => msg.guild_id.map(|gid| gid.0).unwrap_or_default(),
+        "initiator" => format!("({}, {})", &msg.author.name, msg.author.id.0),
+        "unique execution id" => unique_nano())))
+}
+
+/// Пользователь, указанный в аргументах команды
+fn target_user(args: &mut Args) -> UResult<UserId> {
+    args.single::<UserId>().map_err(|_| {
+        let hint = "Укажите пользователя упоминанием или идентификатором".to_owned();
+        BotError::UserInput(UserInputError::InvalidArgument(hint)).into()
+    })
+}
+
+/// Принудительный запуск процесса регистрации для пользователя
+#[command]
+#[num_args(1)]
+async fn signup(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::signup").await?;
+    let gid = msg.guild_id.ok_or(BotError::UserInput(UserInputError::NotInGuild))?;
+    let user = target_user(&mut args)?.to_user(ctx).await?;
+    info!(logger, "Forcing sign up session"; "target" => user.id.0);
+
+    let session_ctx = ctx.clone();
+    let session_user = user.clone();
+    tokio::spawn(async move {
+        if let Err(why) = start_signup_session(&session_ctx, &session_user, &gid).await {
+            let why = BotError::from(why);
+            error!(logger, "Forced sign up session failed"; &why);
+        }
+    });
+
+    let response = MessageBuilder::new()
+        .push("Запускаю регистрацию для ")
+        .mention(&user)
+        .build();
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+/// Ручное одобрение пользователя
+///
+/// Если у пользователя есть заявка, ожидающая рассмотрения,
+/// она одобряется целиком (роль и псевдоним), иначе
+/// пользователю просто выдаётся роль участника
+#[command]
+#[num_args(1)]
+async fn approve(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::approve").await?;
+    let gid = msg.guild_id.ok_or(BotError::UserInput(UserInputError::NotInGuild))?;
+    let uid = target_user(&mut args)?;
+
+    let registry = approval_registry(ctx).await?;
+    if let Some(application) = registry.take_by_user(gid, uid)? {
+        if let Err(why) = approve_application(ctx, &logger, &application).await {
+            registry.insert(application)?;
+            return Err(why);
+        }
+        info!(logger, "Pending application approved manually"; "target" => uid.0, "application" => &application.id);
+    } else {
+        let config = bot_config(ctx).await?;
+        ctx.http
+            .add_member_role(
+                gid.0,
+                uid.0,
+                config.signup.member_role_id,
+                Some("Ручное одобрение администрацией"),
+            )
+            .await?;
+        info!(logger, "Member approved manually"; "target" => uid.0);
+    }
+
+    let response = MessageBuilder::new()
+        .mention(&uid)
+        .push(" одобрен и получил роль участника")
+        .build();
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+/// Изменение псевдонима пользователя в группе
+#[command]
+#[min_args(2)]
+async fn setnick(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::setnick").await?;
+    let gid = msg.guild_id.ok_or(BotError::UserInput(UserInputError::NotInGuild))?;
+    let uid = target_user(&mut args)?;
+    let nickname = validate_nickname(args.rest())
+        .map_err(|hint| BotError::UserInput(UserInputError::InvalidArgument(hint)))?;
+
+    gid.edit_member(&ctx.http, uid, |member| member.nickname(&nickname))
+        .await?;
+    info!(logger, "Member nickname changed"; "target" => uid.0, "nickname" => &nickname);
+
+    let response = MessageBuilder::new()
+        .push("Псевдоним ")
+        .mention(&uid)
+        .push(" изменён на ")
+        .push_bold_safe(&nickname)
+        .build();
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+/// Управление мостом между Discord и Telegram
+#[command]
+#[sub_commands(bridge_pause, bridge_resume)]
+async fn bridge(ctx: &Context, msg: &Message) -> CommandResult {
+    let state = if bridge_state(ctx).await?.is_paused() {
+        "приостановлен"
+    } else {
+        "активен"
+    };
+    let response = format!("Мост сейчас {}. Используйте `bridge pause` или `bridge resume`", state);
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+#[command("pause")]
+async fn bridge_pause(ctx: &Context, msg: &Message) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::bridge").await?;
+    bridge_state(ctx).await?.pause();
+    info!(logger, "Bridge paused");
+    msg.channel_id.say(&ctx.http, "Пересылка сообщений приостановлена").await?;
+    Ok(())
+}
+
+#[command("resume")]
+async fn bridge_resume(ctx: &Context, msg: &Message) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::bridge").await?;
+    bridge_state(ctx).await?.resume();
+    info!(logger, "Bridge resumed");
+    msg.channel_id.say(&ctx.http, "Пересылка сообщений возобновлена").await?;
+    Ok(())
+}
+
+/// Просмотр и изменение конфигурации бота
+#[command("config")]
+#[sub_commands(config_get, config_set)]
+async fn config_cmd(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id
+        .say(&ctx.http, "Используйте `config get <ключ>` или `config set <ключ> <значение>`")
+        .await?;
+    Ok(())
+}
+
+#[command("get")]
+#[num_args(1)]
+async fn config_get(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let key = args.single::<String>()?;
+    let value = bot_config(ctx).await?.get_value(&key)?;
+    let response = MessageBuilder::new()
+        .push_mono_safe(&key)
+        .push(" = ")
+        .push_mono_safe(value.to_string())
+        .build();
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+#[command("set")]
+#[min_args(2)]
+async fn config_set(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::config").await?;
+    let key = args.single::<String>()?;
+    let raw = args.rest().trim().to_owned();
+    let secret = is_secret(&key);
+    if secret {
+        if let Err(why) = msg.delete(&ctx.http).await {
+            let why = BotError::from(why);
+            warn!(logger, "Could not delete the message with a secret"; &why);
+        }
+    }
+
+    {
+        let data = ctx.data.read().await;
+        let config = data
+            .get::<ConfigKey>()
+            .ok_or(BotError::DataNotFound("Bot configuration"))?;
+        let mut config = config.write().await;
+        config.set_value(&key, &raw)?;
+        config.save()?;
+    }
+    let logged = if secret { "<redacted>" } else { raw.as_str() };
+    info!(logger, "Configuration changed"; "key" => &key, "value" => logged);
+
+    let mut response = MessageBuilder::new();
+    response.push("Параметр ").push_mono_safe(&key).push(" обновлён");
+    if requires_restart(&key) {
+        response.push(". Изменение вступит в силу после перезапуска бота");
+    }
+    msg.channel_id.say(&ctx.http, response.build()).await?;
+    Ok(())
+}
+
+/// Список активных сессий регистрации
+#[command]
+#[sub_commands(sessions_cancel)]
+async fn sessions(ctx: &Context, msg: &Message) -> CommandResult {
+    let active = session_registry(ctx).await?.list()?;
+    if active.is_empty() {
+        msg.channel_id.say(&ctx.http, "Активных сессий регистрации нет").await?;
+        return Ok(());
+    }
+
+    let mut response = MessageBuilder::new();
+    response.push_bold_line("Активные сессии регистрации:");
+    for ((_, uid), info) in active {
+        response
+            .push("- ")
+            .mention(&uid)
+            .push(format!(" ({}), начата ", info.user_name))
+            .push_line(info.started_at.format("%d-%m-%Y %H:%M").to_string());
+    }
+    msg.channel_id.say(&ctx.http, response.build()).await?;
+    Ok(())
+}
+
+/// Прерывание активной сессии регистрации пользователя
+#[command("cancel")]
+#[num_args(1)]
+async fn sessions_cancel(ctx: &Context, msg: &Message, mut args: Args) -> CommandResult {
+    let logger = admin_logger(ctx, msg, "command::admin::sessions").await?;
+    let gid = msg.guild_id.ok_or(BotError::UserInput(UserInputError::NotInGuild))?;
+    let uid = target_user(&mut args)?;
+
+    let response = if session_registry(ctx).await?.cancel(gid, uid)? {
+        info!(logger, "Sign up session cancelled"; "target" => uid.0);
+        MessageBuilder::new()
+            .push("Сессия регистрации ")
+            .mention(&uid)
+            .push(" прервана")
+            .build()
+    } else {
+        MessageBuilder::new()
+            .push("У ")
+            .mention(&uid)
+            .push(" нет активной сессии регистрации")
+            .build()
+    };
+    msg.channel_id.say(&ctx.http, response).await?;
+    Ok(())
+}
+
+/// Текущие значения счётчиков событий бота
+#[command("metrics")]
+async fn metrics_cmd(ctx: &Context, msg: &Message) -> CommandResult {
+    let counters = crate::core::metrics::metrics(ctx).await?.snapshot();
+    if counters.is_empty() {
+        msg.channel_id.say(&ctx.http, "Событий пока не было").await?;
+        return Ok(());
+    }
+
+    let mut response = MessageBuilder::new();
+    response.push_bold_line("Счётчики событий:");
+    for (name, value) in counters {
+        response
+            .push("- ")
+            .push_mono_safe(name)
+            .push_line(format!(": {}", value));
+    }
+    msg.channel_id.say(&ctx.http, response.build()).await?;
+    Ok(())
+}
+
+/// Структура с административными командами бота
+#[group]
+#[only_in(guilds)]
+#[checks(Admin)]
+#[commands(signup, approve, setnick, bridge, config_cmd, sessions, metrics_cmd)]
+struct Admin;
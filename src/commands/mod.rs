@@ -0,0 +1,97 @@
+pub mod admin;
+
+use serenity::{
+    framework::standard::{
+        macros::{command, group, hook},
+        CommandResult, DispatchError, Reason,
+    },
+    model::prelude::*,
+    prelude::*,
+};
+
+use crate::core::{guild_rules, rules_signup, SignupDeps};
+use crate::prelude::*;
+
+use slog::o;
+
+/// Команда проверки связи с ботом
+#[command]
+#[bucket = "commands"]
+async fn ping(ctx: &Context, msg: &Message) -> CommandResult {
+    msg.channel_id.say(&ctx.http, "Pong!").await?;
+
+    Ok(())
+}
+
+/// Команда запроса правил сервера и запуска процесса регистрации
+#[command]
+#[only_in(guilds)]
+#[bucket = "signup"]
+async fn rules(ctx: &Context, msg: &Message) -> CommandResult {
+    let gid = msg.guild_id.ok_or(BotError::UserInput(UserInputError::NotInGuild))?;
+    let logger = child_logger(ctx, "command::rules").await?;
+    let logger = logger.new(o!(
+            "guild id" => gid.0,
+            "initiator" => format!("({}, {})", &msg.author.name, msg.author.id.0),
+            "unique execution id" => unique_nano()));
+    info!(logger, "Executing 'rules' command");
+
+    let deps = SignupDeps::from_context(ctx).await?;
+    debug!(logger, "Starting sign up session");
+    match rules_signup(&deps.env(), msg.channel_id, &msg.author, &gid, guild_rules()).await {
+        Ok(()) => info!(logger, "Successfully passed registration process"),
+        // Пользователю уже сообщили о причине
+        Err(why) => error!(logger, "Could not successfully register the user"; &why),
+    }
+    Ok(())
+}
+
+/// Обработчик ошибок запуска команд
+///
+/// Сообщает пользователю о причине, по которой команда
+/// не была выполнена (недостаток прав, аргументов и т.п.)
+#[hook]
+pub async fn dispatch_error_hook(ctx: &Context, msg: &Message, error: DispatchError, command_name: &str) {
+    let response = match error {
+        DispatchError::CheckFailed(_, Reason::User(reason))
+        | DispatchError::CheckFailed(_, Reason::UserAndLog { user: reason, .. }) => reason,
+        DispatchError::CheckFailed(..) => "У вас недостаточно прав для этой команды".to_owned(),
+        DispatchError::NotEnoughArguments { min, given } => {
+            format!("Команде `{}` нужно аргументов: {}, передано: {}", command_name, min, given)
+        }
+        DispatchError::TooManyArguments { max, given } => {
+            format!("Команда `{}` принимает аргументов: {}, передано: {}", command_name, max, given)
+        }
+        DispatchError::OnlyForGuilds => "Эта команда доступна только на сервере".to_owned(),
+        DispatchError::Ratelimited(info) if info.is_first_try => {
+            format!("Не так часто! Попробуй снова через {} сек.", info.as_secs())
+        }
+        _ => return,
+    };
+    let _ = msg.channel_id.say(&ctx.http, response).await;
+}
+
+/// Обработчик ошибок выполнения команд
+///
+/// Записывает ошибку команды в лог и сообщает пользователю
+/// её причину. Команды, которые сами сообщают об ошибках,
+/// завершаются успешно
+#[hook]
+pub async fn after_hook(ctx: &Context, msg: &Message, command_name: &str, result: CommandResult) {
+    let why = match result {
+        Ok(()) => return,
+        Err(why) => BotError::from(why).with_context(format!("Executing '{}' command", command_name)),
+    };
+    if let Ok(logger) = child_logger(ctx, "command::after").await {
+        error!(logger, "Command failed";
+            "command" => command_name,
+            "initiator" => format!("({}, {})", &msg.author.name, msg.author.id.0),
+            &why);
+    }
+    let _ = msg.channel_id.say(&ctx.http, why.user_message()).await;
+}
+
+/// Структура с основными командами бота
+#[group]
+#[commands(ping, rules)]
+struct General;
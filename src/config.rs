@@ -0,0 +1,570 @@
+use crate::core::auth::AuthMode;
+use crate::core::transform::TelegramMarkup;
+use crate::core::transport::TransportKind;
+use crate::core::questionnaire::{self, Question};
+use crate::core::roles::RoleRule;
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::prelude::{Context, RwLock, TypeMapKey};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Путь к файлу конфигурации бота
+pub const CONFIG_PATH: &str = "config.json";
+
+/// Параметры, значения которых не показываются и не пишутся в лог
+const SECRET_KEYS: &[&str] = &["bridge.auth.secret"];
+
+/// Параметры, которые применяются только при запуске бота
+const RESTART_KEYS: &[&str] = &[
+    "general.prefix",
+    "general.presences",
+    "limits",
+    "storage",
+    "bridge.transport",
+    "bridge.socket",
+    "bridge.auth",
+];
+
+/// Замена значения скрытого параметра
+const REDACTED: &str = "<скрыто>";
+
+/// Признак пересечения путей параметров
+///
+/// Пути пересекаются, если один из них совпадает с другим
+/// или вложен в него
+fn overlaps(key: &str, other: &str) -> bool {
+    let nested = |outer: &str, inner: &str| {
+        inner.strip_prefix(outer).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    };
+    nested(key, other) || nested(other, key)
+}
+
+/// Признак того, что путь указывает на секрет или содержит его
+pub fn is_secret(key: &str) -> bool {
+    SECRET_KEYS.iter().any(|secret| overlaps(key, secret))
+}
+
+/// Признак того, что изменение параметра требует перезапуска бота
+pub fn requires_restart(key: &str) -> bool {
+    RESTART_KEYS.iter().any(|restart| overlaps(key, restart))
+}
+
+/// Конфигурация бота
+///
+/// Загружается из файла `config.json` в рабочей директории.
+/// Любое отсутствующее поле принимает значение по умолчанию,
+/// поэтому файл может содержать только изменённые параметры.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BotConfig {
+    pub general: GeneralConfig,
+    pub signup: SignupConfig,
+    pub bridge: BridgeConfig,
+    pub admin: AdminConfig,
+    pub storage: StorageConfig,
+    pub reporting: ReportingConfig,
+    pub limits: LimitsConfig,
+}
+
+/// Общие параметры бота
+///
+/// `presences` включает привилегированное намерение
+/// `GUILD_PRESENCES`, без которого статусы присутствия
+/// участников неизвестны. Его также нужно разрешить на
+/// портале разработчиков Discord
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GeneralConfig {
+    pub prefix: String,
+    pub guild_id: u64,
+    pub presences: bool,
+}
+
+/// Параметры процесса регистрации
+///
+/// Если у пользователя закрыты личные сообщения, регистрация
+/// проходит в приватной ветке канала `onboarding_channel_id`.
+/// Нулевой идентификатор отключает такую возможность.
+/// `reply_timeout_secs` ограничивает время ожидания ответа
+/// на каждый вопрос, если для вопроса не задано своё.
+/// При `session_takeover` повторный запуск регистрации
+/// прерывает предыдущую сессию, иначе он отклоняется
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SignupConfig {
+    pub member_role_id: u64,
+    pub welcome_channel_id: u64,
+    pub onboarding_channel_id: u64,
+    pub reply_timeout_secs: u64,
+    pub session_takeover: bool,
+    pub approval: ApprovalConfig,
+    pub questions: Vec<Question>,
+    pub role_rules: Vec<RoleRule>,
+}
+
+/// Параметры ручного одобрения заявок
+///
+/// Если одобрение включено, заявка нового участника
+/// публикуется в канале администрации, и роль выдаётся
+/// только после её одобрения
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApprovalConfig {
+    pub enabled: bool,
+    pub staff_channel_id: u64,
+}
+
+/// Параметры моста между Discord и Telegram
+///
+/// Слова из `word_filter` заменяются звёздочками в
+/// пересылаемых сообщениях в обоих направлениях. Сообщения
+/// ботов и вебхуков пересылаются, только если их
+/// идентификатор указан в `allowed_bot_ids`.
+/// `telegram_markup` должна совпадать с разметкой, в
+/// которой работает бот Telegram
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgeConfig {
+    pub channel_id: u64,
+    pub word_filter: Vec<String>,
+    pub telegram_markup: TelegramMarkup,
+    pub allowed_bot_ids: Vec<u64>,
+    pub transport: TransportConfig,
+    pub socket: SocketConfig,
+    pub auth: BridgeAuthConfig,
+    pub announcements: AnnouncementsConfig,
+}
+
+/// Объявления об активности участников в мосте
+///
+/// Вход в голосовые каналы из `voice_channel_ids` и выход
+/// из них объявляются в мосте. При `games` объявляется
+/// запуск игры, для этого нужно включить `general.presences`.
+/// Одинаковые объявления отправляются не чаще, чем раз в
+/// `debounce_secs` секунд
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnnouncementsConfig {
+    pub voice_channel_ids: Vec<u64>,
+    pub games: bool,
+    pub debounce_secs: u64,
+}
+
+/// Транспорт моста
+///
+/// При `kind` равном `unix` используются сокеты из
+/// `socket`. При `tcp` бот принимает команды на
+/// `listen_addr` и отправляет их на `peer_addr`. Адрес,
+/// отличный от localhost, требует режима подписи `required`
+/// или `tls.client_auth`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TransportConfig {
+    pub kind: TransportKind,
+    pub listen_addr: String,
+    pub peer_addr: String,
+    pub tls: TlsConfig,
+}
+
+/// Параметры TLS транспорта TCP
+///
+/// `cert_path` и `key_path` - сертификат и ключ сервера
+/// команд в формате PEM. Сертификат собеседника
+/// проверяется по `ca_path` на соответствие `server_name`
+/// (по умолчанию - узлу из `peer_addr`). При `client_auth`
+/// стороны предъявляют друг другу свои сертификаты, и
+/// сервер принимает только подписанные `ca_path`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub cert_path: String,
+    pub key_path: String,
+    pub ca_path: String,
+    pub server_name: String,
+    pub client_auth: bool,
+}
+
+/// Параметры подписи команд моста
+///
+/// Команды подписываются общим с ботом Telegram секретом
+/// `secret`. Команды старше `max_age_secs` секунд
+/// отклоняются. Режим `migration` принимает и
+/// неподписанные команды, `required` - только подписанные
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgeAuthConfig {
+    pub secret: String,
+    pub mode: AuthMode,
+    pub max_age_secs: u64,
+}
+
+/// Параметры сокетов моста
+///
+/// По умолчанию сокеты располагаются в директории
+/// `qcorsar/<instance>` внутри `runtime_dir`, а если она не
+/// задана - внутри `$XDG_RUNTIME_DIR`. Уже существующая
+/// директория должна принадлежать пользователю бота и быть
+/// недоступна остальным. `listen_path` и `peer_path`
+/// позволяют указать пути явно. При
+/// `check_peer_uid` сервер принимает подключения только от
+/// пользователей из `allowed_uids` или, если список пуст,
+/// от пользователя, под которым запущен бот
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SocketConfig {
+    pub runtime_dir: String,
+    pub instance: String,
+    pub listen_path: String,
+    pub peer_path: String,
+    pub check_peer_uid: bool,
+    pub allowed_uids: Vec<u32>,
+}
+
+/// Параметры хранения данных бота
+///
+/// В `applications_path` хранятся заявки, ожидающие решения
+/// администрации, чтобы их можно было рассмотреть после
+/// перезапуска бота
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub members_path: String,
+    pub applications_path: String,
+}
+
+/// Параметры оповещения администрации об ошибках
+///
+/// Нулевой идентификатор канала отключает оповещения.
+/// Об одинаковых ошибках администрация уведомляется не чаще
+/// раза в `throttle_secs` секунд
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReportingConfig {
+    pub staff_channel_id: u64,
+    pub throttle_secs: u64,
+}
+
+/// Ограничения частоты команд и пересылки сообщений
+///
+/// `signup` ограничивает команду `rules` для каждого
+/// пользователя, `commands` - прочие общие команды в каждом
+/// канале. Эти ограничения применяются при запуске бота.
+/// `bridge` ограничивает пересылку сообщений через мост
+/// для каждого автора и канала
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub signup: BucketConfig,
+    pub commands: BucketConfig,
+    pub bridge: BridgeLimitsConfig,
+}
+
+/// Параметры корзины команд Serenity
+///
+/// `delay_secs` - минимальный интервал между вызовами,
+/// `limit` - число вызовов за `time_span_secs` секунд
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BucketConfig {
+    pub delay_secs: u64,
+    pub time_span_secs: u64,
+    pub limit: u32,
+}
+
+/// Ограничения пересылки сообщений через мост
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BridgeLimitsConfig {
+    pub user: TokenBucketConfig,
+    pub channel: TokenBucketConfig,
+}
+
+/// Параметры корзины токенов
+///
+/// Нулевая ёмкость отключает ограничение
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenBucketConfig {
+    pub capacity: u32,
+    pub refill_per_minute: u32,
+}
+
+/// Параметры доступа к административным командам
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    pub role_ids: Vec<u64>,
+}
+
+impl Default for GeneralConfig {
+    fn default() -> Self {
+        Self {
+            prefix: "!".to_owned(),
+            guild_id: 1032941443058241546,
+            presences: false,
+        }
+    }
+}
+
+impl Default for SignupConfig {
+    fn default() -> Self {
+        Self {
+            member_role_id: 1037417494178181231,
+            welcome_channel_id: 0,
+            onboarding_channel_id: 0,
+            reply_timeout_secs: 120,
+            session_takeover: true,
+            approval: Default::default(),
+            questions: Vec::new(),
+            role_rules: Vec::new(),
+        }
+    }
+}
+
+impl Default for BridgeConfig {
+    fn default() -> Self {
+        Self {
+            channel_id: 1032942368015515708,
+            word_filter: Vec::new(),
+            telegram_markup: Default::default(),
+            allowed_bot_ids: Vec::new(),
+            transport: Default::default(),
+            socket: Default::default(),
+            auth: Default::default(),
+            announcements: Default::default(),
+        }
+    }
+}
+
+impl Default for AnnouncementsConfig {
+    fn default() -> Self {
+        Self {
+            voice_channel_ids: Vec::new(),
+            games: false,
+            debounce_secs: 300,
+        }
+    }
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        Self {
+            kind: TransportKind::Unix,
+            listen_addr: "127.0.0.1:7451".to_owned(),
+            peer_addr: "127.0.0.1:7452".to_owned(),
+            tls: Default::default(),
+        }
+    }
+}
+
+impl Default for BridgeAuthConfig {
+    fn default() -> Self {
+        Self {
+            secret: String::new(),
+            mode: AuthMode::Migration,
+            max_age_secs: 300,
+        }
+    }
+}
+
+impl Default for SocketConfig {
+    fn default() -> Self {
+        Self {
+            runtime_dir: String::new(),
+            instance: "default".to_owned(),
+            listen_path: String::new(),
+            peer_path: String::new(),
+            check_peer_uid: true,
+            allowed_uids: Vec::new(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        Self {
+            members_path: "members.json".to_owned(),
+            applications_path: "applications.json".to_owned(),
+        }
+    }
+}
+
+impl Default for ReportingConfig {
+    fn default() -> Self {
+        Self {
+            staff_channel_id: 0,
+            throttle_secs: 600,
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            signup: BucketConfig {
+                delay_secs: 30,
+                time_span_secs: 600,
+                limit: 3,
+            },
+            commands: BucketConfig {
+                delay_secs: 1,
+                time_span_secs: 10,
+                limit: 5,
+            },
+            bridge: Default::default(),
+        }
+    }
+}
+
+impl Default for BucketConfig {
+    fn default() -> Self {
+        Self {
+            delay_secs: 0,
+            time_span_secs: 0,
+            limit: 1,
+        }
+    }
+}
+
+impl Default for BridgeLimitsConfig {
+    fn default() -> Self {
+        Self {
+            user: TokenBucketConfig {
+                capacity: 5,
+                refill_per_minute: 20,
+            },
+            channel: TokenBucketConfig {
+                capacity: 20,
+                refill_per_minute: 60,
+            },
+        }
+    }
+}
+
+impl BotConfig {
+    /// Сохранение конфигурации в файл
+    pub fn save(&self) -> UResult {
+        let content = serde_json::to_string_pretty(self).map_err(|why| BotError::Config(why.to_string()))?;
+        std::fs::write(CONFIG_PATH, content).map_err(|why| BotError::Storage(why.into()))?;
+        Ok(())
+    }
+
+    /// Получение значения параметра по его пути
+    ///
+    /// Путь задаётся через точку, например `signup.member_role_id`.
+    /// Непустые значения секретов заменяются заглушкой
+    pub fn get_value(&self, key: &str) -> UResult<Value> {
+        let mut root = serde_json::to_value(self)?;
+        for secret in SECRET_KEYS {
+            let pointer = format!("/{}", secret.replace('.', "/"));
+            if let Some(value) = root.pointer_mut(&pointer) {
+                if value.as_str() != Some("") {
+                    *value = Value::String(REDACTED.to_owned());
+                }
+            }
+        }
+        key.split('.')
+            .try_fold(&root, |node, part| node.get(part))
+            .cloned()
+            .ok_or(BotError::UserInput(UserInputError::UnknownConfigKey(key.to_owned())).into())
+    }
+
+    /// Изменение значения параметра по его пути
+    ///
+    /// Значение интерпретируется как JSON, а в случае неудачи
+    /// как обычная строка. Секреты всегда сохраняются как
+    /// строки. Итоговая конфигурация проверяется на
+    /// корректность перед применением.
+    pub fn set_value(&mut self, key: &str, raw: &str) -> UResult {
+        let value = if SECRET_KEYS.contains(&key) {
+            Value::String(raw.to_owned())
+        } else {
+            serde_json::from_str(raw).unwrap_or(Value::String(raw.to_owned()))
+        };
+        let mut root = serde_json::to_value(&*self)?;
+        let node = key
+            .split('.')
+            .try_fold(&mut root, |node, part| node.get_mut(part))
+            .ok_or(BotError::UserInput(UserInputError::UnknownConfigKey(key.to_owned())))?;
+        *node = value;
+        let config: BotConfig = serde_json::from_value(root)
+            .map_err(|why| BotError::UserInput(UserInputError::InvalidArgument(why.to_string())))?;
+        config
+            .validate()
+            .map_err(|why| BotError::UserInput(UserInputError::InvalidArgument(why)))?;
+        *self = config;
+        Ok(())
+    }
+
+    /// Проверка согласованности параметров
+    pub fn validate(&self) -> Result<(), String> {
+        questionnaire::validate_questions(&self.signup.questions)
+    }
+}
+
+/// Загрузка конфигурации бота
+///
+/// Если файл конфигурации отсутствует, используются
+/// значения по умолчанию. Несогласованная конфигурация
+/// (например, анкета с переходом на несуществующий вопрос)
+/// отклоняется
+pub fn load_config() -> UResult<BotConfig> {
+    let path = Path::new(CONFIG_PATH);
+    if !path.exists() {
+        return Ok(BotConfig::default());
+    }
+    let content = std::fs::read_to_string(path)
+        .map_err(|why| BotError::Config(format!("Reading {}: {}", CONFIG_PATH, why)))?;
+    let config: BotConfig = serde_json::from_str(&content).map_err(|why| BotError::Config(why.to_string()))?;
+    config.validate().map_err(BotError::Config)?;
+    Ok(config)
+}
+
+pub struct ConfigKey;
+impl TypeMapKey for ConfigKey {
+    type Value = Arc<RwLock<BotConfig>>;
+}
+
+/// Получение копии текущей конфигурации бота
+pub async fn bot_config(ctx: &Context) -> UResult<BotConfig> {
+    let data = ctx.data.read().await;
+    let config = data
+        .get::<ConfigKey>()
+        .ok_or(BotError::DataNotFound("Bot configuration"))?;
+    let config = config.read().await;
+    Ok(config.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn secrets_are_hidden_from_config_output() {
+        let mut config = BotConfig::default();
+        config.set_value("bridge.auth.secret", "123456").unwrap();
+        assert_eq!(config.bridge.auth.secret, "123456");
+
+        let hidden = serde_json::Value::String("<скрыто>".to_owned());
+        assert_eq!(config.get_value("bridge.auth.secret").unwrap(), hidden);
+        assert_eq!(config.get_value("bridge").unwrap()["auth"]["secret"], hidden);
+        assert!(!config.get_value("bridge").unwrap().to_string().contains("123456"));
+
+        assert!(is_secret("bridge") && is_secret("bridge.auth.secret"));
+        assert!(!is_secret("bridge.auth.mode") && !is_secret("bridge.authority"));
+    }
+
+    #[test]
+    fn startup_parameters_require_a_restart() {
+        assert!(requires_restart("general.prefix"));
+        assert!(requires_restart("limits.bridge.user.capacity"));
+        assert!(requires_restart("bridge.auth.secret"));
+        assert!(requires_restart("bridge"));
+        assert!(!requires_restart("general.guild_id"));
+        assert!(!requires_restart("bridge.word_filter"));
+        assert!(!requires_restart("reporting.staff_channel_id"));
+    }
+}
@@ -0,0 +1,224 @@
+use crate::prelude::*;
+use serenity::{model::prelude::*, prelude::*};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Событие активности участника для объявления в мосте
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Activity {
+    VoiceJoined(ChannelId),
+    VoiceLeft(ChannelId),
+    GameStarted(String),
+}
+
+impl Activity {
+    /// Ключ для подавления повторных объявлений
+    fn key(&self, user_id: UserId) -> String {
+        match self {
+            Self::VoiceJoined(channel) => format!("voice-join:{}:{}", user_id.0, channel.0),
+            Self::VoiceLeft(channel) => format!("voice-leave:{}:{}", user_id.0, channel.0),
+            Self::GameStarted(game) => format!("game:{}:{}", user_id.0, game.to_lowercase()),
+        }
+    }
+
+    /// Текст объявления
+    ///
+    /// `channel_name` - имя голосового канала, если оно известно
+    pub fn text(&self, member: &str, channel_name: Option<&str>) -> String {
+        let channel = |id: &ChannelId| match channel_name {
+            Some(name) => format!("«{}»", name),
+            None => format!("#{}", id.0),
+        };
+        match self {
+            Self::VoiceJoined(id) => format!("🎙 {} заходит в голосовой канал {}", member, channel(id)),
+            Self::VoiceLeft(id) => format!("🔇 {} выходит из голосового канала {}", member, channel(id)),
+            Self::GameStarted(game) => format!("🎮 {} запускает {}", member, game),
+        }
+    }
+}
+
+/// Объявления о входе в голосовые каналы и выходе из них
+///
+/// Объявляются только каналы из списка `voice_channel_ids`
+pub fn voice_activities(
+    config: &AnnouncementsConfig,
+    old_channel: Option<ChannelId>,
+    new_channel: Option<ChannelId>,
+) -> Vec<Activity> {
+    if old_channel == new_channel {
+        return Vec::new();
+    }
+    let announced = |channel: &ChannelId| config.voice_channel_ids.contains(&channel.0);
+    old_channel
+        .filter(announced)
+        .map(Activity::VoiceLeft)
+        .into_iter()
+        .chain(new_channel.filter(announced).map(Activity::VoiceJoined))
+        .collect()
+}
+
+/// Состояние объявлений об активности
+///
+/// Хранит время последнего объявления каждого вида и
+/// последнюю игру каждого участника, чтобы не объявлять
+/// одно и то же событие чаще, чем раз в `debounce_secs`
+#[derive(Debug, Default)]
+pub struct AnnouncementState {
+    announced: Mutex<HashMap<String, Instant>>,
+    games: Mutex<HashMap<UserId, String>>,
+}
+
+impl AnnouncementState {
+    /// Разрешение объявить событие
+    pub fn allow(&self, user_id: UserId, activity: &Activity, debounce: Duration) -> bool {
+        let mut announced = match self.announced.lock() {
+            Ok(value) => value,
+            Err(_) => return false,
+        };
+        let now = Instant::now();
+        announced.retain(|_, at| now.duration_since(*at) < debounce);
+        let key = activity.key(user_id);
+        if announced.contains_key(&key) {
+            return false;
+        }
+        announced.insert(key, now);
+        true
+    }
+
+    /// Запоминание текущей игры участника
+    ///
+    /// Возвращает игру, если участник только что её запустил
+    pub fn game_started(&self, user_id: UserId, current: Option<&str>) -> Option<String> {
+        let mut games = self.games.lock().ok()?;
+        match current {
+            Some(game) => {
+                let previous = games.insert(user_id, game.to_owned());
+                (previous.as_deref() != Some(game)).then(|| game.to_owned())
+            }
+            None => {
+                games.remove(&user_id);
+                None
+            }
+        }
+    }
+}
+
+pub struct AnnouncementsKey;
+impl TypeMapKey for AnnouncementsKey {
+    type Value = Arc<AnnouncementState>;
+}
+
+/// Получение состояния объявлений об активности
+pub async fn announcement_state(ctx: &Context) -> UResult<Arc<AnnouncementState>> {
+    let data = ctx.data.read().await;
+    data.get::<AnnouncementsKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Announcement state").into())
+}
+
+/// Игра, запущенная участником, из его статуса присутствия
+pub fn current_game(presence: &Presence) -> Option<&str> {
+    presence
+        .activities
+        .iter()
+        .find(|activity| activity.kind == ActivityType::Playing)
+        .map(|activity| activity.name.as_str())
+}
+
+/// Отправка объявления об активности участника в мост
+///
+/// Объявление пропускается, если мост приостановлен или
+/// такое же объявление уже было недавно
+pub async fn announce(ctx: &Context, gid: GuildId, user_id: UserId, activity: Activity) -> UResult<bool> {
+    let config = bot_config(ctx).await?;
+    let state = super::bridge::bridge_state(ctx).await?;
+    if state.is_paused() {
+        return Ok(false);
+    }
+    let debounce = Duration::from_secs(config.bridge.announcements.debounce_secs);
+    if !announcement_state(ctx).await?.allow(user_id, &activity, debounce) {
+        super::metrics::metrics(ctx).await?.increment("announcements.debounced");
+        return Ok(false);
+    }
+
+    let member = match ctx.cache.member(gid, user_id) {
+        Some(member) => member.display_name().into_owned(),
+        None => user_id.to_user(ctx).await?.name,
+    };
+    let channel_name = match &activity {
+        Activity::VoiceJoined(id) | Activity::VoiceLeft(id) => ctx.cache.guild_channel(*id).map(|c| c.name),
+        Activity::GameStarted(_) => None,
+    };
+    let content = activity.text(&member, channel_name.as_deref());
+    let cmd = super::bridge::forward_command(state.origin_for_event("activity"), "Discord".to_owned(), content);
+    super::bridge::send_to_telegram(ctx, cmd).await?;
+    super::metrics::metrics(ctx).await?.increment("announcements.sent");
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn announcements_config(channels: &[u64]) -> AnnouncementsConfig {
+        AnnouncementsConfig {
+            voice_channel_ids: channels.to_vec(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn voice_activities_follow_channel_opt_in() {
+        let config = announcements_config(&[10]);
+        let (announced, quiet) = (ChannelId(10), ChannelId(20));
+
+        assert_eq!(
+            voice_activities(&config, None, Some(announced)),
+            vec![Activity::VoiceJoined(announced)]
+        );
+        assert!(voice_activities(&config, None, Some(quiet)).is_empty());
+        assert_eq!(
+            voice_activities(&config, Some(announced), Some(quiet)),
+            vec![Activity::VoiceLeft(announced)]
+        );
+        assert!(voice_activities(&config, Some(announced), Some(announced)).is_empty());
+
+        let both = announcements_config(&[10, 20]);
+        assert_eq!(
+            voice_activities(&both, Some(quiet), Some(announced)),
+            vec![Activity::VoiceLeft(quiet), Activity::VoiceJoined(announced)]
+        );
+        assert!(voice_activities(&announcements_config(&[]), None, Some(announced)).is_empty());
+    }
+
+    #[test]
+    fn repeated_announcements_are_debounced() {
+        let state = AnnouncementState::default();
+        let joined = Activity::VoiceJoined(ChannelId(10));
+
+        assert!(state.allow(UserId(1), &joined, Duration::from_secs(60)));
+        assert!(!state.allow(UserId(1), &joined, Duration::from_secs(60)));
+        assert!(state.allow(UserId(2), &joined, Duration::from_secs(60)));
+        assert!(state.allow(UserId(1), &Activity::VoiceLeft(ChannelId(10)), Duration::from_secs(60)));
+
+        assert!(state.allow(UserId(3), &joined, Duration::ZERO));
+        assert!(state.allow(UserId(3), &joined, Duration::ZERO));
+    }
+
+    #[test]
+    fn only_newly_started_games_are_announced() {
+        let state = AnnouncementState::default();
+
+        assert_eq!(state.game_started(UserId(1), Some("Quake")), Some("Quake".to_owned()));
+        assert_eq!(state.game_started(UserId(1), Some("Quake")), None);
+        assert_eq!(state.game_started(UserId(1), None), None);
+        assert_eq!(state.game_started(UserId(1), Some("Quake")), Some("Quake".to_owned()));
+        assert_eq!(state.game_started(UserId(1), Some("Doom")), Some("Doom".to_owned()));
+
+        let text = Activity::GameStarted("Doom".to_owned()).text("Innri", None);
+        assert_eq!(text, "🎮 Innri запускает Doom");
+        let text = Activity::VoiceJoined(ChannelId(10)).text("Innri", Some("Общий"));
+        assert_eq!(text, "🎙 Innri заходит в голосовой канал «Общий»");
+    }
+}
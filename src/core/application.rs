@@ -1,26 +1,42 @@
 use crate::prelude::*;
 use qcproto::prelude::*;
 use serenity::prelude::{TypeMapKey, Context};
-use serenity::utils::MessageBuilder;
+use serenity::framework::standard::buckets::LimitedFor;
 use serenity::{framework::StandardFramework, model::prelude::*, Client};
-use slog::{crit, debug, info, Logger};
+use slog::Logger;
 use std::collections::HashMap;
 use std::future::Future;
 use std::marker::PhantomData;
 use std::sync::Arc;
-use serde_json::{json, Value};
+use serde_json::Value;
 use std::thread;
 use std::sync::RwLock;
 
 use std::sync::mpsc;
 
-const CRATE_VERSION: &'static str = env!("CARGO_PKG_VERSION");
-const TOKEN_ENV: &'static str = "QUEENSCORSAR_TOKEN";
+use super::announcements::{AnnouncementState, AnnouncementsKey};
+use super::approval::{ApprovalRegistry, ApprovalsKey};
+use super::auth::{BridgeAuth, BridgeAuthKey};
+use super::requests::{BridgeResponse, RequestHandler, SignedRequest};
+use super::bridge::{BridgeState, BridgeStateKey, InboundSource};
+use super::channels::{ChannelCache, ChannelsKey};
+use super::ratelimit::{BridgeLimiter, BridgeLimiterKey};
+use super::reporter::{ErrorThrottle, ErrorThrottleKey};
+use super::sessions::{SessionRegistry, SessionsKey};
+use super::socket;
+use super::transport::{self, BridgeSender, BridgeServer, Connection, TcpCommandServer, TransportKind, UnixCommandServer};
+use super::metrics::{Metrics, MetricsKey};
+use super::storage::{MemberStorage, StorageKey};
+use super::webhooks::{WebhookCache, WebhooksKey};
+use crate::commands::admin::ADMIN_GROUP;
+
+const CRATE_VERSION: &str = env!("CARGO_PKG_VERSION");
+const TOKEN_ENV: &str = "QUEENSCORSAR_TOKEN";
 
 #[derive(Clone)]
 pub struct BootstrapRequirements {
     pub logger: slog::Logger,
-    // pub config: config::Config,
+    pub config: BotConfig,
 }
 
 #[derive(Debug)]
@@ -52,13 +68,13 @@ impl<ST, RT> Pipe<ST, RT>
     pub fn send(&self, data: ST) -> UResult
     {
         let lock = self.s.write();
-        if let Err(why) = lock {
-            return Err("RwLock on sender seems to be poisoned".into());
+        if lock.is_err() {
+            return Err(BotError::Internal("RwLock on sender seems to be poisoned".into()).into());
         }
         let lock = lock.unwrap();
 
-        if let Err(why) = lock.send(data) {
-            Err("Receiver doesn't seem to exist anymore".into())
+        if lock.send(data).is_err() {
+            Err(BotError::Internal("Receiver doesn't seem to exist anymore".into()).into())
         } else {
             Ok(())
         }
@@ -66,23 +82,19 @@ impl<ST, RT> Pipe<ST, RT>
 
     pub fn recv(&self) -> UResult<RT> {
         let lock = self.r.write();
-        if let Err(why) = lock {
-            return Err("RwLock on receiver seems to be poisoned".into());
+        if lock.is_err() {
+            return Err(BotError::Internal("RwLock on receiver seems to be poisoned".into()).into());
         }
         let lock = lock.unwrap();
 
-        let res = lock.recv();
-        if let Err(ref why) = res {
-            Err("Sender doesn't seem to exist anymore".into())
-        } else {
-            Ok(res.unwrap())
-        }
+        lock.recv()
+            .map_err(|_| BotError::Internal("Sender doesn't seem to exist anymore".into()).into())
     }
 }
 
 pub struct SendersKey;
 impl TypeMapKey for SendersKey {
-    type Value = HashMap<String, CommandSender>;
+    type Value = HashMap<String, Arc<BridgeSender>>;
 }
 
 
@@ -97,47 +109,53 @@ impl<T> TypeMapKey for PipesKey<T>
     type Value = HashMap<String, Pipe<T>>;
 }
 
-struct DsCommandHandler {
+/// Обработчик команд и запросов из моста
+///
+/// Команды принимаются через [`super::bridge::receive`] с
+/// зависимостями из `source`: в работе это контекст
+/// Discord, в тестах - заранее собранный [`super::bridge::Inbound`]
+pub struct DsCommandHandler<S> {
     logger: Logger,
-    ds_context: Context,
+    source: Arc<S>,
     async_runtime: tokio::runtime::Runtime
 }
 
-impl DsCommandHandler {
-    pub fn new(logger: Logger, ds_context: Context, async_runtime: tokio::runtime::Runtime) -> Self {
-        Self { logger, ds_context, async_runtime }
+impl<S> DsCommandHandler<S> {
+    pub fn new(logger: Logger, source: Arc<S>, async_runtime: tokio::runtime::Runtime) -> Self {
+        Self { logger, source, async_runtime }
     }
 }
 
-impl CommandHandler for DsCommandHandler {
+impl<S: InboundSource + 'static> CommandHandler for DsCommandHandler<S> {
     fn forward_message(&self, msg: Command) -> UResult {
         info!(self.logger, "Forwarding the message: {:#?}", msg);
-        let ds_context = self.ds_context.clone();
+        let source = self.source.clone();
+        let logger = self.logger.clone();
         self.as_sync(async move {
-            let guild = ds_context.http.get_guild(1032941443058241546).await?;
-            let channel_id = ChannelId(1032942368015515708);
-            let channels = guild.channels(&ds_context.http).await?;
-            let channel = channels.get(&channel_id).unwrap();
-            if let CommandKind::ForwardMessage { from, to: _, content } = msg.kind {
-                let msg_content = MessageBuilder::new()
-                    .push_bold_safe(from.name)
-                    .push_line_safe(" пишет:")
-                    .push_safe(content)
-                    .build();
-                channel.send_message(&ds_context.http, |m| {
-                    m.content(msg_content)
-                }).await?;
-                UResult::Ok(())
-            } else {
-                UResult::Err("".into())
+            let inbound = source.inbound().await?;
+            super::bridge::receive(&inbound, &logger, msg).await
+        })?
+    }
+}
+
+impl RequestHandler for DsCommandHandler<Context> {
+    fn handle_request(&self, request: SignedRequest) -> BridgeResponse {
+        let ds_context = self.source.clone();
+        let logger = self.logger.clone();
+        let response = self.as_sync(async move { super::requests::respond(&ds_context, &logger, request).await });
+        match response {
+            Ok(Ok(response)) => response,
+            Ok(Err(why)) | Err(why) => {
+                warn!(self.logger, "Could not answer a bridge request"; "reason" => format!("{:?}", why));
+                BridgeResponse::Error {
+                    message: BotError::from(why).to_string(),
+                }
             }
-        })??;
-        // let _ = self.ds_context.http.send_message("1034419827525296191".parse::<u64>(), "");
-        Ok(())
+        }
     }
 }
 
-impl DsCommandHandler {
+impl<S> DsCommandHandler<S> {
     fn as_sync<F>(&self, f: F) -> UResult<<F as Future>::Output>
         where F: Future + Send + 'static,
               F::Output: Send + 'static
@@ -147,29 +165,69 @@ impl DsCommandHandler {
     }
 }
 
-pub fn bootstrap_command_server(ctx: &BootstrapRequirements, comm: Pipe<Context>) -> UResult<CommandServer> {
-    let srv_addr = format!(
-        "{}",
-        "/tmp/qcorsar.discord.sock".to_owned()
-        // ctx.config.general.sock_addr.to_string_lossy().into_owned()
-    );
+/// Сборка сервера команд моста с указанным обработчиком
+///
+/// Транспорт выбирается по конфигурации моста. Используется
+/// как для рабочего сервера, так и для тестового собеседника
+/// моста
+pub fn build_command_server<H>(
+    config: &BridgeConfig,
+    handler: Arc<H>,
+    metrics: Arc<Metrics>,
+    logger: &Logger,
+) -> UResult<BridgeServer>
+    where H: CommandHandler + RequestHandler + Send + Sync + 'static
+{
+    let request_handler: Arc<dyn RequestHandler + Send + Sync> = handler.clone();
+    let command_dispatcher = Arc::new(DefaultCommandDispatcher::new(
+        handler,
+        logger.clone(),
+    ));
+    let connection = Connection::new(command_dispatcher, request_handler, metrics, logger.clone());
+    match config.transport.kind {
+        TransportKind::Unix => {
+            let socket_config = &config.socket;
+            if socket_config.listen_path.trim().is_empty() {
+                socket::prepare_socket_dir(socket_config)?;
+            }
+            let srv_addr = socket::listen_path(socket_config);
+            let allowed_uids = socket_config
+                .check_peer_uid
+                .then(|| socket::allowed_uids(socket_config));
+            info!(logger, "Starting the bridge command server";
+                "socket" => srv_addr.display().to_string(),
+                "allowed uids" => format!("{:?}", allowed_uids),
+            );
+            let server = UnixCommandServer::bind(&srv_addr, connection, allowed_uids)?;
+            Ok(BridgeServer::Unix(server))
+        }
+        TransportKind::Tcp => {
+            let tcp = &config.transport;
+            info!(logger, "Starting the bridge command server";
+                "address" => &tcp.listen_addr,
+                "tls" => tcp.tls.enabled,
+            );
+            transport::check_exposure(config)?;
+            if !tcp.tls.enabled {
+                warn!(logger, "The bridge TCP transport is not encrypted, enable TLS unless both halves share a trusted network");
+            }
+            let tls = if tcp.tls.enabled {
+                Some(transport::server_tls(&tcp.tls)?)
+            } else {
+                None
+            };
+            let server = BridgeServer::Tcp(TcpCommandServer::bind(&tcp.listen_addr, connection, tls)?);
+            info!(logger, "Bridge command server is listening"; "address" => format!("{:?}", server.local_addr()));
+            Ok(server)
+        }
+    }
+}
 
+pub fn bootstrap_command_server(ctx: &BootstrapRequirements, comm: Pipe<Context>, metrics: Arc<Metrics>) -> UResult<BridgeServer> {
     let ds_context = comm.recv()?;
     let runtime = tokio::runtime::Runtime::new()?;
-    let command_handler = Arc::new(DsCommandHandler::new(ctx.logger.clone(), ds_context, runtime));
-    let command_dispatcher = Arc::new(DefaultCommandDispatcher::new(
-        command_handler,
-        ctx.logger.clone(),
-    ));
-    let stream_handler = Arc::new(DefaultUnixStreamHandler::new(
-        command_dispatcher,
-        ctx.logger.clone(),
-    ));
-    CommandServer::new()
-        .logger(ctx.logger.clone())
-        .server_addr(&srv_addr)
-        .stream_handler(stream_handler)
-        .build()
+    let command_handler = Arc::new(DsCommandHandler::new(ctx.logger.clone(), Arc::new(ds_context), runtime));
+    build_command_server(&ctx.config.bridge, command_handler, metrics, &ctx.logger)
 }
 
 pub async fn bootstrap_application(ctx: BootstrapRequirements) -> UResult {
@@ -195,19 +253,42 @@ pub async fn bootstrap_application(ctx: BootstrapRequirements) -> UResult {
         "Successfully retrieved Discord API token from the environment"
     );
 
-    let prefix = "!";
+    let prefix = ctx.config.general.prefix.clone();
+    let limits = ctx.config.limits.clone();
     let framework = StandardFramework::new()
-        .configure(|c| c.prefix(prefix))
-        .group(&GENERAL_GROUP);
+        .configure(|c| c.prefix(&prefix))
+        .bucket("signup", |b| {
+            b.delay(limits.signup.delay_secs)
+                .time_span(limits.signup.time_span_secs)
+                .limit(limits.signup.limit)
+                .limit_for(LimitedFor::User)
+        })
+        .await
+        .bucket("commands", |b| {
+            b.delay(limits.commands.delay_secs)
+                .time_span(limits.commands.time_span_secs)
+                .limit(limits.commands.limit)
+                .limit_for(LimitedFor::Channel)
+        })
+        .await
+        .on_dispatch_error(dispatch_error_hook)
+        .after(after_hook)
+        .group(&GENERAL_GROUP)
+        .group(&ADMIN_GROUP);
     debug!(ctx.logger, "Serenity standard framework initialized";
         "options" => format!("{:#?}", GENERAL_GROUP.options),
-        "group" => &GENERAL_GROUP.name,
-        "prefix" => "!",
+        "groups" => format!("{}, {}", GENERAL_GROUP.name, ADMIN_GROUP.name),
+        "prefix" => &prefix,
     );
 
-    let intents = GatewayIntents::non_privileged()
+    let mut intents = GatewayIntents::non_privileged()
         | GatewayIntents::MESSAGE_CONTENT
         | GatewayIntents::GUILD_MEMBERS;
+    if ctx.config.general.presences {
+        intents |= GatewayIntents::GUILD_PRESENCES;
+    } else if ctx.config.bridge.announcements.games {
+        warn!(ctx.logger, "Game announcements are enabled but presences are not, no games will be announced");
+    }
     debug!(ctx.logger, "Gateway intents initialized"; "intents" => format!("{:#?}", intents));
 
     let mut loggers_map: HashMap<String, Logger> = HashMap::new();
@@ -215,10 +296,59 @@ pub async fn bootstrap_application(ctx: BootstrapRequirements) -> UResult {
         .entry("root".to_owned())
         .or_insert(ctx.logger.clone());
 
+    let storage = match MemberStorage::open(&ctx.config.storage.members_path) {
+        Ok(value) => value,
+        Err(why) => {
+            crit!(ctx.logger, "Could not open the member storage";
+                "reason" => format!("{:?}", why),
+                "path" => &ctx.config.storage.members_path
+            );
+            return Err(why);
+        }
+    };
+
+    let approvals = match ApprovalRegistry::open(&ctx.config.storage.applications_path) {
+        Ok(value) => value,
+        Err(why) => {
+            crit!(ctx.logger, "Could not open the pending applications";
+                "reason" => format!("{:?}", why),
+                "path" => &ctx.config.storage.applications_path
+            );
+            return Err(why);
+        }
+    };
+
+    let bridge_auth = match BridgeAuth::new(&ctx.config.bridge.auth) {
+        Ok(value) => value,
+        Err(why) => {
+            crit!(ctx.logger, "Could not configure bridge command signing";
+                "reason" => format!("{:?}", why)
+            );
+            return Err(why);
+        }
+    };
+    info!(ctx.logger, "Bridge command signing configured";
+        "mode" => format!("{:?}", bridge_auth.mode()),
+    );
+    let bridge_auth = Arc::new(bridge_auth);
+    let metrics = Arc::new(Metrics::default());
+
     let mut client = match Client::builder(token, intents)
-        .event_handler(Handler)
+        .event_handler(Handler::new(ctx.logger.clone()))
         .framework(framework)
         .type_map_insert::<LoggersKey>(loggers_map)
+        .type_map_insert::<ConfigKey>(Arc::new(tokio::sync::RwLock::new(ctx.config.clone())))
+        .type_map_insert::<SessionsKey>(Arc::new(SessionRegistry::default()))
+        .type_map_insert::<BridgeStateKey>(Arc::new(BridgeState::default()))
+        .type_map_insert::<BridgeAuthKey>(bridge_auth.clone())
+        .type_map_insert::<AnnouncementsKey>(Arc::new(AnnouncementState::default()))
+        .type_map_insert::<BridgeLimiterKey>(Arc::new(BridgeLimiter::new(&ctx.config.limits.bridge)))
+        .type_map_insert::<ApprovalsKey>(Arc::new(approvals))
+        .type_map_insert::<StorageKey>(Arc::new(storage))
+        .type_map_insert::<MetricsKey>(metrics.clone())
+        .type_map_insert::<ErrorThrottleKey>(Arc::new(ErrorThrottle::default()))
+        .type_map_insert::<WebhooksKey>(Arc::new(WebhookCache::default()))
+        .type_map_insert::<ChannelsKey>(Arc::new(ChannelCache::default()))
         .await
     {
         Ok(c) => c,
@@ -238,6 +368,7 @@ pub async fn bootstrap_application(ctx: BootstrapRequirements) -> UResult {
         let (cs_side, ds_side) = Pipe::<Context>::channel();
 
         let logger = ctx.logger.clone();
+        let tg_sender = Arc::new(BridgeSender::from_config(&ctx.config.bridge, bridge_auth)?);
         let client_thread = runtime.spawn(async move {
             {
                 let mut data = client.data.write().await;
@@ -245,8 +376,7 @@ pub async fn bootstrap_application(ctx: BootstrapRequirements) -> UResult {
                 pipes.insert("cmdserver".to_owned(), ds_side);
 
                 let senders = data.entry::<SendersKey>().or_insert(HashMap::new());
-                let sender = CommandSender::new("/tmp/qcorsar.tg.sock".to_owned());
-                senders.insert("tgsender".to_owned(), sender);
+                senders.insert("tgsender".to_owned(), tg_sender);
             }
             if let Err(why) = client.start().await {
                 crit!(logger, "A critical error occured while running serenity client"; "reason" => format!("{:?}", why));
@@ -262,10 +392,10 @@ pub async fn bootstrap_application(ctx: BootstrapRequirements) -> UResult {
 
         let logger = ctx.logger.clone();
         let _ = scope.spawn(move || -> UResult {
-            let cmd_server = bootstrap_command_server(&ctx, cs_side)?;
+            let cmd_server = bootstrap_command_server(&ctx, cs_side, metrics)?;
             if let Err(why) = cmd_server.listen() {
                 crit!(logger, "Command server failed to run correctly; reason: {:#?}", why);
-                Err(why.into())
+                Err(why)
             } else {
                 Ok(())
             }
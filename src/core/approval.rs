@@ -0,0 +1,434 @@
+use crate::prelude::*;
+use serenity::model::application::component::{ActionRowComponent, ButtonStyle, InputTextStyle};
+use serenity::model::application::interaction::{
+    message_component::MessageComponentInteraction, modal::ModalSubmitInteraction,
+    InteractionResponseType,
+};
+use serenity::{model::prelude::*, prelude::*, utils::MessageBuilder};
+use serde::{Deserialize, Serialize};
+use slog::Logger;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::conversation::ConversationChannel;
+use super::discord::{DiscordOps, SerenityOps};
+use super::questionnaire::Answer;
+use super::roles::{apply_roles, plan_roles};
+use super::storage::{member_storage, MemberRecord, MemberStorage};
+
+const APPROVE_PREFIX: &str = "signup_approve:";
+const REJECT_PREFIX: &str = "signup_reject:";
+const REASON_PREFIX: &str = "signup_reason:";
+const REASON_INPUT_ID: &str = "reason";
+
+/// Ответ администрации на кнопку заявки, которой нет в реестре
+const UNKNOWN_APPLICATION: &str = "Заявка уже была рассмотрена или больше не действительна";
+
+/// Заявка пользователя на вступление в гильдию
+///
+/// `channel` - канал, в котором проходила регистрация. Если
+/// личные сообщения пользователя закрыты, решение по заявке
+/// сообщается туда
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Application {
+    pub id: String,
+    pub guild_id: GuildId,
+    pub user_id: UserId,
+    pub user_name: String,
+    pub channel: ConversationChannel,
+    pub nickname: String,
+    pub answers: Vec<Answer>,
+}
+
+impl Application {
+    pub fn new(
+        gid: GuildId,
+        user: &User,
+        channel: ConversationChannel,
+        nickname: String,
+        answers: Vec<Answer>,
+    ) -> Self {
+        Self {
+            id: unique_nano(),
+            guild_id: gid,
+            user_id: user.id,
+            user_name: user.name.clone(),
+            channel,
+            nickname,
+            answers,
+        }
+    }
+}
+
+/// Реестр заявок, ожидающих решения администрации
+///
+/// Открытый по пути реестр хранит заявки в JSON-файле и
+/// полностью перезаписывает его при каждом изменении, так
+/// что кнопки заявок работают и после перезапуска бота.
+/// Реестр по умолчанию хранит заявки только в памяти
+#[derive(Debug, Default)]
+pub struct ApprovalRegistry {
+    path: Option<PathBuf>,
+    pending: RwLock<HashMap<String, Application>>,
+}
+
+impl ApprovalRegistry {
+    /// Открытие реестра по указанному пути
+    ///
+    /// Если файл отсутствует, реестр создаётся пустым
+    pub fn open<P: AsRef<Path>>(path: P) -> UResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let pending = if path.exists() {
+            let content = std::fs::read_to_string(&path).map_err(|why| BotError::Storage(why.into()))?;
+            serde_json::from_str(&content).map_err(|why| BotError::Storage(why.into()))?
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            path: Some(path),
+            pending: RwLock::new(pending),
+        })
+    }
+
+    fn save(&self, pending: &HashMap<String, Application>) -> UResult {
+        let path = match &self.path {
+            Some(value) => value,
+            None => return Ok(()),
+        };
+        let content = serde_json::to_string_pretty(pending).map_err(|why| BotError::Storage(why.into()))?;
+        std::fs::write(path, content).map_err(|why| BotError::Storage(why.into()))?;
+        Ok(())
+    }
+
+    pub fn insert(&self, application: Application) -> UResult {
+        let mut pending = match self.pending.write() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("RwLock on pending applications seems to be poisoned".into()).into()),
+        };
+        pending.insert(application.id.clone(), application);
+        self.save(&pending)
+    }
+
+    /// Извлечение заявки из реестра по её идентификатору
+    pub fn take(&self, id: &str) -> UResult<Option<Application>> {
+        let mut pending = match self.pending.write() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("RwLock on pending applications seems to be poisoned".into()).into()),
+        };
+        let application = pending.remove(id);
+        if application.is_some() {
+            self.save(&pending)?;
+        }
+        Ok(application)
+    }
+
+    /// Извлечение заявки из реестра по пользователю
+    pub fn take_by_user(&self, gid: GuildId, uid: UserId) -> UResult<Option<Application>> {
+        let mut pending = match self.pending.write() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("RwLock on pending applications seems to be poisoned".into()).into()),
+        };
+        let id = pending
+            .values()
+            .find(|a| a.guild_id == gid && a.user_id == uid)
+            .map(|a| a.id.clone());
+        let application = id.and_then(|id| pending.remove(&id));
+        if application.is_some() {
+            self.save(&pending)?;
+        }
+        Ok(application)
+    }
+}
+
+pub struct ApprovalsKey;
+impl TypeMapKey for ApprovalsKey {
+    type Value = Arc<ApprovalRegistry>;
+}
+
+/// Получение реестра заявок, ожидающих решения
+pub async fn approval_registry(ctx: &Context) -> UResult<Arc<ApprovalRegistry>> {
+    let data = ctx.data.read().await;
+    data.get::<ApprovalsKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Approval registry").into())
+}
+
+/// Публикация заявки в канале администрации
+///
+/// Заявка оформляется в виде вставки с ответами пользователя
+/// и кнопками одобрения и отклонения
+pub async fn post_application(ctx: &Context, staff_channel: ChannelId, application: &Application) -> UResult {
+    let user = application.user_id.to_user(ctx).await?;
+    staff_channel
+        .send_message(&ctx.http, |m| {
+            m.embed(|e| {
+                e.title("Новая заявка на вступление")
+                    .description(MessageBuilder::new().mention(&user).build())
+                    .thumbnail(user.face())
+                    .field("Ник в игре", &application.nickname, false);
+                for answer in &application.answers {
+                    e.field(&answer.prompt, &answer.value, false);
+                }
+                e
+            })
+            .components(|c| {
+                c.create_action_row(|r| {
+                    r.create_button(|b| {
+                        b.custom_id(format!("{}{}", APPROVE_PREFIX, application.id))
+                            .label("Одобрить")
+                            .style(ButtonStyle::Success)
+                    })
+                    .create_button(|b| {
+                        b.custom_id(format!("{}{}", REJECT_PREFIX, application.id))
+                            .label("Отклонить")
+                            .style(ButtonStyle::Danger)
+                    })
+                })
+            })
+        })
+        .await?;
+    Ok(())
+}
+
+/// Передача заявки на рассмотрение администрации
+///
+/// Заявка публикуется в канале `staff_channel` и хранится
+/// в реестре до решения
+pub async fn submit_application(
+    ops: &dyn DiscordOps,
+    staff_channel: ChannelId,
+    registry: &ApprovalRegistry,
+    application: Application,
+) -> UResult {
+    ops.post_application(staff_channel, &application).await?;
+    registry.insert(application)
+}
+
+/// Завершение регистрации по заявке
+///
+/// Назначает пользователю роли по ответам анкеты, устанавливает
+/// псевдоним и сохраняет запись об участнике
+pub async fn complete_signup(
+    ops: &dyn DiscordOps,
+    config: &SignupConfig,
+    storage: &MemberStorage,
+    application: &Application,
+) -> UResult {
+    let gid = application.guild_id;
+    let uid = application.user_id;
+    let plan = plan_roles(config, &application.answers);
+    apply_roles(ops, &gid, uid, &plan).await?;
+    ops.set_nickname(gid, uid, &application.nickname).await?;
+
+    storage.upsert(MemberRecord {
+        guild_id: gid.0,
+        user_id: uid.0,
+        user_name: application.user_name.clone(),
+        nickname: application.nickname.clone(),
+        answers: application.answers.clone(),
+        registered_at: chrono::Local::now().to_rfc3339(),
+    })
+}
+
+/// Сообщение пользователю о решении по заявке
+///
+/// Сообщение отправляется в личные сообщения, а если они
+/// закрыты - в ветку, где проходила регистрация. Решение к
+/// этому моменту уже применено, поэтому ошибка доставки
+/// только записывается в лог
+async fn notify_applicant(ctx: &Context, logger: &Logger, application: &Application, msg: &str) {
+    let delivered = match application.user_id.to_user(ctx).await {
+        Ok(user) => send_privately(ctx, &user, msg).await,
+        Err(why) => Err(why.into()),
+    };
+    let delivered = match (delivered, application.channel) {
+        (Err(why), ConversationChannel::Thread(thread)) => {
+            debug!(logger, "Could not notify the applicant privately, posting in the onboarding thread";
+                "reason" => why.to_string());
+            SerenityOps::new(ctx).send_message(thread, msg).await
+        }
+        (delivered, _) => delivered,
+    };
+    if let Err(why) = delivered {
+        warn!(logger, "Could not notify the applicant about the decision";
+            "user id" => application.user_id.0,
+            "reason" => why.to_string(),
+        );
+    }
+}
+
+/// Одобрение заявки
+///
+/// Выдаёт пользователю роль и псевдоним, после чего
+/// сообщает ему о результате рассмотрения
+pub async fn approve_application(ctx: &Context, logger: &Logger, application: &Application) -> UResult {
+    let config = bot_config(ctx).await?;
+    let storage = member_storage(ctx).await?;
+    complete_signup(&SerenityOps::new(ctx), &config.signup, &storage, application).await?;
+    let msg = MessageBuilder::new()
+        .push_bold_line_safe(
+            "Твоя заявка одобрена! Тебе выдана роль в группе и поставлен псевдоним. Приятной игры!",
+        )
+        .build();
+    notify_applicant(ctx, logger, application, &msg).await;
+    Ok(())
+}
+
+/// Отклонение заявки с сообщением причины пользователю
+pub async fn reject_application(ctx: &Context, logger: &Logger, application: &Application, reason: Option<&str>) {
+    let mut msg = MessageBuilder::new();
+    msg.push_bold_line_safe("К сожалению, твоя заявка на вступление была отклонена.");
+    if let Some(reason) = reason {
+        msg.push("Причина: ").push_line_safe(reason);
+    }
+    notify_applicant(ctx, logger, application, &msg.build()).await;
+}
+
+/// Обработка нажатия кнопок одобрения и отклонения заявки
+///
+/// Ответ на нажатие откладывается до применения решения.
+/// Если одобрить заявку не получилось, она возвращается в
+/// реестр и её можно рассмотреть повторно
+pub async fn handle_component(ctx: &Context, logger: &Logger, component: &MessageComponentInteraction) -> UResult {
+    let custom_id = component.data.custom_id.as_str();
+    if !custom_id.starts_with(APPROVE_PREFIX) && !custom_id.starts_with(REJECT_PREFIX) {
+        return Ok(());
+    }
+
+    let is_admin = match &component.member {
+        Some(member) => member_is_admin(ctx, member).await?,
+        None => false,
+    };
+    if !is_admin {
+        component
+            .create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::ChannelMessageWithSource)
+                    .interaction_response_data(|d| {
+                        d.content("Рассматривать заявки может только администрация гильдии")
+                            .ephemeral(true)
+                    })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    if let Some(id) = custom_id.strip_prefix(REJECT_PREFIX) {
+        component
+            .create_interaction_response(&ctx.http, |r| {
+                r.kind(InteractionResponseType::Modal)
+                    .interaction_response_data(|d| {
+                        d.custom_id(format!("{}{}", REASON_PREFIX, id))
+                            .title("Отклонение заявки")
+                            .components(|c| {
+                                c.create_action_row(|r| {
+                                    r.create_input_text(|t| {
+                                        t.custom_id(REASON_INPUT_ID)
+                                            .label("Причина (необязательно)")
+                                            .style(InputTextStyle::Paragraph)
+                                            .required(false)
+                                    })
+                                })
+                            })
+                    })
+            })
+            .await?;
+        return Ok(());
+    }
+
+    component.defer(&ctx.http).await?;
+    let id = custom_id.trim_start_matches(APPROVE_PREFIX);
+    let registry = approval_registry(ctx).await?;
+    let outcome = match registry.take(id)? {
+        Some(application) => {
+            if let Err(why) = approve_application(ctx, logger, &application).await {
+                registry.insert(application)?;
+                component
+                    .create_followup_message(&ctx.http, |m| {
+                        m.content("Не получилось одобрить заявку, попробуй ещё раз").ephemeral(true)
+                    })
+                    .await?;
+                return Err(why);
+            }
+            MessageBuilder::new()
+                .push("Заявка одобрена: ")
+                .mention(&component.user)
+                .build()
+        }
+        None => UNKNOWN_APPLICATION.to_owned(),
+    };
+    component
+        .edit_original_interaction_response(&ctx.http, |r| r.content(outcome).components(|c| c))
+        .await?;
+    Ok(())
+}
+
+/// Обработка формы с причиной отклонения заявки
+pub async fn handle_modal(ctx: &Context, logger: &Logger, modal: &ModalSubmitInteraction) -> UResult {
+    let id = match modal.data.custom_id.strip_prefix(REASON_PREFIX) {
+        Some(value) => value,
+        None => return Ok(()),
+    };
+    modal.defer(&ctx.http).await?;
+
+    let reason = modal
+        .data
+        .components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .find_map(|component| match component {
+            ActionRowComponent::InputText(input) if input.custom_id == REASON_INPUT_ID => {
+                Some(input.value.trim().to_owned())
+            }
+            _ => None,
+        })
+        .filter(|reason| !reason.is_empty());
+
+    let outcome = match approval_registry(ctx).await?.take(id)? {
+        Some(application) => {
+            reject_application(ctx, logger, &application, reason.as_deref()).await;
+            let mut outcome = MessageBuilder::new();
+            outcome.push("Заявка отклонена: ").mention(&modal.user);
+            if let Some(reason) = &reason {
+                outcome.push(", причина: ").push_safe(reason);
+            }
+            outcome.build()
+        }
+        None => UNKNOWN_APPLICATION.to_owned(),
+    };
+    modal
+        .edit_original_interaction_response(&ctx.http, |r| r.content(outcome).components(|c| c))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn application(id: &str) -> Application {
+        Application {
+            id: id.to_owned(),
+            guild_id: GuildId(1),
+            user_id: UserId(2),
+            user_name: "innri".to_owned(),
+            channel: ConversationChannel::Direct(ChannelId(3)),
+            nickname: "Инри".to_owned(),
+            answers: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn pending_applications_survive_a_restart() {
+        let path = std::env::temp_dir().join(format!("qcorsar.applications.{}.json", unique_nano()));
+        ApprovalRegistry::open(&path).unwrap().insert(application("first")).unwrap();
+
+        let registry = ApprovalRegistry::open(&path).unwrap();
+        let restored = registry.take("first").unwrap().unwrap();
+        assert_eq!(restored.user_id, UserId(2));
+        assert_eq!(restored.nickname, "Инри");
+
+        assert!(ApprovalRegistry::open(&path).unwrap().take("first").unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
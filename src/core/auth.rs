@@ -0,0 +1,396 @@
+use crate::prelude::*;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serenity::prelude::*;
+use sha2::Sha256;
+use slog::Logger;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::metrics::Metrics;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Версия формата подписи команд
+pub const SIGNATURE_VERSION: &str = "v1";
+
+/// Режим проверки подписи команд моста
+///
+/// В режиме `migration` неподписанные команды принимаются
+/// с записью в журнал аудита, в режиме `required` они
+/// отклоняются. Неверно подписанные команды отклоняются
+/// в обоих режимах. Запросы к справочнику участников
+/// принимаются только подписанными (см.
+/// [`super::requests::is_permitted`])
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthMode {
+    #[default]
+    Migration,
+    Required,
+}
+
+/// Причина отклонения команды моста
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthFailure {
+    Unsigned,
+    Malformed,
+    BadSignature,
+    Expired,
+    Replayed,
+}
+
+impl AuthFailure {
+    /// Имя счётчика отклонённых команд
+    pub fn metric(&self) -> &'static str {
+        match self {
+            Self::Unsigned => "bridge.auth.unsigned",
+            Self::Malformed => "bridge.auth.malformed",
+            Self::BadSignature => "bridge.auth.bad_signature",
+            Self::Expired => "bridge.auth.expired",
+            Self::Replayed => "bridge.auth.replayed",
+        }
+    }
+}
+
+impl std::fmt::Display for AuthFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsigned => write!(f, "command is not signed"),
+            Self::Malformed => write!(f, "command signature is malformed"),
+            Self::BadSignature => write!(f, "command signature does not match"),
+            Self::Expired => write!(f, "command timestamp is outside of the allowed window"),
+            Self::Replayed => write!(f, "command nonce was already used"),
+        }
+    }
+}
+
+/// Результат проверки команды моста
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verification {
+    Signed,
+    /// Неподписанная команда, принятая в режиме миграции
+    UnsignedAccepted,
+    Rejected(AuthFailure),
+}
+
+/// Подпись и проверка команд моста
+///
+/// Подпись передаётся в поле `name` получателя команды в
+/// виде `v1:<время>:<nonce>:<HMAC-SHA256>`, так как формат
+/// команд qcproto не содержит отдельного поля для неё.
+/// HMAC вычисляется от времени, nonce, версии протокола и
+/// остальных полей команды. Запросы к боту подписываются
+/// так же (см. [`BridgeAuth::verify_payload`]). Повторное использование nonce
+/// в пределах окна `max_age_secs` считается атакой повтора
+pub struct BridgeAuth {
+    secret: Option<Vec<u8>>,
+    mode: AuthMode,
+    max_age_secs: u64,
+    nonces: Mutex<HashMap<String, u64>>,
+}
+
+impl BridgeAuth {
+    /// Создание из конфигурации моста
+    ///
+    /// Обязательная подпись без общего секрета является
+    /// ошибкой конфигурации
+    pub fn new(config: &BridgeAuthConfig) -> UResult<Self> {
+        let secret = match config.secret.trim() {
+            "" => None,
+            secret => Some(secret.as_bytes().to_vec()),
+        };
+        if secret.is_none() && config.mode == AuthMode::Required {
+            return Err(BotError::Config("Bridge command signing is required but no secret is set".to_owned()).into());
+        }
+        Ok(Self {
+            secret,
+            mode: config.mode,
+            max_age_secs: config.max_age_secs,
+            nonces: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn mode(&self) -> AuthMode {
+        self.mode
+    }
+
+    /// Подпись команды, если задан общий секрет
+    pub fn sign(&self, cmd: &mut Command) {
+        self.sign_at(cmd, unix_now(), &unique_nano());
+    }
+
+    fn sign_at(&self, cmd: &mut Command, timestamp: u64, nonce: &str) {
+        let tag = match command_payload(cmd).and_then(|(fields, body)| self.tag(&fields, &body, timestamp, nonce)) {
+            Some(tag) => tag,
+            None => return,
+        };
+        if let CommandKind::ForwardMessage { to, .. } = &mut cmd.kind {
+            to.name = tag;
+        }
+    }
+
+    /// Проверка подписи и свежести команды
+    pub fn verify(&self, cmd: &Command) -> Verification {
+        self.verify_at(cmd, unix_now())
+    }
+
+    fn verify_at(&self, cmd: &Command, now: u64) -> Verification {
+        match (&cmd.kind, command_payload(cmd)) {
+            (CommandKind::ForwardMessage { to, .. }, Some((fields, body))) => {
+                self.verify_tag(&to.name, &fields, &body, now)
+            }
+            _ => self.verify_tag("", &[], "", now),
+        }
+    }
+
+    /// Проверка подписи произвольного содержимого вида `kind`
+    pub fn verify_payload(&self, kind: &str, body: &str, tag: &str) -> Verification {
+        self.verify_tag(tag, &[kind.to_owned()], body, unix_now())
+    }
+
+    /// Метка подписи `v1:<время>:<nonce>:<HMAC>`
+    fn tag(&self, fields: &[String], body: &str, timestamp: u64, nonce: &str) -> Option<String> {
+        let secret = self.secret.as_ref()?;
+        let mac = signing_mac(secret, fields, body, timestamp, nonce)?;
+        Some(format!(
+            "{}:{}:{}:{}",
+            SIGNATURE_VERSION,
+            timestamp,
+            nonce,
+            to_hex(&mac.finalize().into_bytes())
+        ))
+    }
+
+    fn verify_tag(&self, tag: &str, fields: &[String], body: &str, now: u64) -> Verification {
+        if !tag.starts_with(SIGNATURE_VERSION) {
+            return match self.mode {
+                AuthMode::Migration => Verification::UnsignedAccepted,
+                AuthMode::Required => Verification::Rejected(AuthFailure::Unsigned),
+            };
+        }
+        let secret = match &self.secret {
+            Some(secret) => secret,
+            // Без секрета подпись проверить нельзя, в режиме миграции команда принимается
+            None => return Verification::UnsignedAccepted,
+        };
+
+        let mut parts = tag.splitn(4, ':').skip(1);
+        let (timestamp, nonce, mac) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(timestamp), Some(nonce), Some(mac)) if !nonce.is_empty() => (timestamp, nonce, mac),
+            _ => return Verification::Rejected(AuthFailure::Malformed),
+        };
+        let (timestamp, mac) = match (timestamp.parse::<u64>(), from_hex(mac)) {
+            (Ok(timestamp), Some(mac)) => (timestamp, mac),
+            _ => return Verification::Rejected(AuthFailure::Malformed),
+        };
+
+        let expected = match signing_mac(secret, fields, body, timestamp, nonce) {
+            Some(expected) => expected,
+            None => return Verification::Rejected(AuthFailure::Malformed),
+        };
+        if expected.verify_slice(&mac).is_err() {
+            return Verification::Rejected(AuthFailure::BadSignature);
+        }
+        if now.abs_diff(timestamp) > self.max_age_secs {
+            return Verification::Rejected(AuthFailure::Expired);
+        }
+        if !self.remember_nonce(nonce, timestamp, now) {
+            return Verification::Rejected(AuthFailure::Replayed);
+        }
+        Verification::Signed
+    }
+
+    /// Запоминание nonce, возвращает `false` для уже использованного
+    fn remember_nonce(&self, nonce: &str, timestamp: u64, now: u64) -> bool {
+        let mut nonces = match self.nonces.lock() {
+            Ok(nonces) => nonces,
+            Err(_) => return false,
+        };
+        let max_age = self.max_age_secs;
+        nonces.retain(|_, seen_at| now.abs_diff(*seen_at) <= max_age);
+        if nonces.contains_key(nonce) {
+            return false;
+        }
+        nonces.insert(nonce.to_owned(), timestamp);
+        true
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Подписываемые поля и тело команды
+///
+/// Возвращает `None` для команд, которые не подписываются
+fn command_payload(cmd: &Command) -> Option<(Vec<String>, String)> {
+    match &cmd.kind {
+        CommandKind::ForwardMessage { from, to, content } => Some((
+            vec![
+                cmd.protocol_version.to_string(),
+                from.server.clone(),
+                from.name.clone(),
+                to.server.clone(),
+            ],
+            content.clone(),
+        )),
+        _ => None,
+    }
+}
+
+/// HMAC версии подписи, времени, nonce, полей и тела
+fn signing_mac(secret: &[u8], fields: &[String], body: &str, timestamp: u64, nonce: &str) -> Option<HmacSha256> {
+    let mut mac = HmacSha256::new_from_slice(secret).ok()?;
+    let header = [SIGNATURE_VERSION.to_owned(), timestamp.to_string(), nonce.to_owned()];
+    for field in header.iter().chain(fields) {
+        mac.update(field.as_bytes());
+        mac.update(&[0]);
+    }
+    mac.update(body.as_bytes());
+    Some(mac)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| hex.get(i..i + 2).and_then(|byte| u8::from_str_radix(byte, 16).ok()))
+        .collect()
+}
+
+/// Запись результата проверки в журнал аудита
+///
+/// Возвращает `false`, если команду или запрос нужно
+/// отбросить
+pub fn admit(metrics: &Metrics, audit: &Logger, verification: Verification) -> bool {
+    match verification {
+        Verification::Signed => true,
+        Verification::UnsignedAccepted => {
+            warn!(audit, "Accepted an unsigned bridge command in migration mode");
+            metrics.increment("bridge.auth.unsigned_accepted");
+            true
+        }
+        Verification::Rejected(why) => {
+            warn!(audit, "Rejected a bridge command"; "reason" => why.to_string());
+            metrics.increment(why.metric());
+            false
+        }
+    }
+}
+
+pub struct BridgeAuthKey;
+impl TypeMapKey for BridgeAuthKey {
+    type Value = Arc<BridgeAuth>;
+}
+
+/// Получение подписи команд моста
+pub async fn bridge_auth(ctx: &Context) -> UResult<Arc<BridgeAuth>> {
+    let data = ctx.data.read().await;
+    data.get::<BridgeAuthKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Bridge authentication").into())
+}
+
+#[cfg(test)]
+impl BridgeAuth {
+    /// Подпись произвольного содержимого вида `kind`
+    ///
+    /// Возвращает пустую строку, если общий секрет не задан.
+    /// На стороне бота запросы только проверяются
+    pub fn sign_payload(&self, kind: &str, body: &str) -> String {
+        self.tag(&[kind.to_owned()], body, unix_now(), &unique_nano())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::bridge::forward_command;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn bridge_auth(secret: &str, mode: AuthMode) -> BridgeAuth {
+        BridgeAuth::new(&BridgeAuthConfig {
+            secret: secret.to_owned(),
+            mode,
+            max_age_secs: 300,
+        })
+        .unwrap()
+    }
+
+    fn signed_command(auth: &BridgeAuth, nonce: &str) -> Command {
+        let mut cmd = forward_command("telegram_server_id".to_owned(), "tg".to_owned(), "Привет".to_owned());
+        auth.sign_at(&mut cmd, NOW, nonce);
+        cmd
+    }
+
+    #[test]
+    fn signed_commands_are_accepted_once() {
+        let auth = bridge_auth("secret", AuthMode::Required);
+        let cmd = signed_command(&auth, "nonce");
+
+        assert_eq!(auth.verify_at(&cmd, NOW + 10), Verification::Signed);
+        assert_eq!(
+            auth.verify_at(&cmd, NOW + 20),
+            Verification::Rejected(AuthFailure::Replayed)
+        );
+    }
+
+    #[test]
+    fn tampered_and_stale_commands_are_rejected() {
+        let auth = bridge_auth("secret", AuthMode::Migration);
+        let mut cmd = signed_command(&auth, "tampered");
+        if let CommandKind::ForwardMessage { content, .. } = &mut cmd.kind {
+            content.push_str(" @everyone");
+        }
+        assert_eq!(
+            auth.verify_at(&cmd, NOW),
+            Verification::Rejected(AuthFailure::BadSignature)
+        );
+
+        let foreign = bridge_auth("other secret", AuthMode::Migration);
+        let cmd = signed_command(&foreign, "foreign");
+        assert_eq!(
+            auth.verify_at(&cmd, NOW),
+            Verification::Rejected(AuthFailure::BadSignature)
+        );
+
+        let cmd = signed_command(&auth, "stale");
+        assert_eq!(
+            auth.verify_at(&cmd, NOW + 301),
+            Verification::Rejected(AuthFailure::Expired)
+        );
+    }
+
+    #[test]
+    fn unsigned_commands_depend_on_the_mode() {
+        let cmd = forward_command("telegram_server_id".to_owned(), "tg".to_owned(), "Привет".to_owned());
+
+        let migration = bridge_auth("secret", AuthMode::Migration);
+        assert_eq!(migration.verify_at(&cmd, NOW), Verification::UnsignedAccepted);
+
+        let required = bridge_auth("secret", AuthMode::Required);
+        assert_eq!(
+            required.verify_at(&cmd, NOW),
+            Verification::Rejected(AuthFailure::Unsigned)
+        );
+
+        let config = BridgeAuthConfig {
+            mode: AuthMode::Required,
+            ..Default::default()
+        };
+        assert!(BridgeAuth::new(&config).is_err());
+    }
+}
@@ -0,0 +1,331 @@
+use crate::prelude::*;
+use serenity::{async_trait, model::prelude::*, prelude::*};
+use slog::{o, Logger};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use super::application::SendersKey;
+use super::auth::BridgeAuth;
+use super::discord::{DiscordOps, SerenityOps};
+use super::metrics::Metrics;
+use super::ratelimit::BridgeLimiter;
+
+/// Префикс метки происхождения сообщений из Discord
+///
+/// Метка передаётся в поле `server` отправителя команды
+/// в виде `discord:<экземпляр бота>:<идентификатор сообщения>`
+pub const ORIGIN_PREFIX: &str = "discord";
+
+/// Состояние моста между Discord и Telegram
+#[derive(Debug)]
+pub struct BridgeState {
+    paused: AtomicBool,
+    instance: String,
+}
+
+impl Default for BridgeState {
+    fn default() -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            instance: unique_nano(),
+        }
+    }
+}
+
+impl BridgeState {
+    /// Метка происхождения пересылаемого сообщения
+    pub fn origin_for(&self, message_id: MessageId) -> String {
+        format!("{}:{}:{}", ORIGIN_PREFIX, self.instance, message_id.0)
+    }
+
+    /// Метка происхождения объявления о событии в Discord
+    pub fn origin_for_event(&self, event: &str) -> String {
+        format!("{}:{}:{}", ORIGIN_PREFIX, self.instance, event)
+    }
+
+    /// Признак того, что команда уже прошла через мост
+    ///
+    /// Сообщения, пришедшие из Discord, не должны
+    /// возвращаться в Discord
+    pub fn has_traversed(&self, origin: &str) -> bool {
+        origin
+            .split(':')
+            .next()
+            .is_some_and(|prefix| prefix == ORIGIN_PREFIX)
+    }
+
+    /// Приостановка пересылки сообщений в обе стороны
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Возобновление пересылки сообщений
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+}
+
+/// Команда пересылки сообщения из Discord в Telegram
+///
+/// `origin` - метка происхождения сообщения
+/// (см. [`BridgeState::origin_for`])
+pub fn forward_command(origin: String, author: String, content: String) -> Command {
+    Command {
+        kind: CommandKind::ForwardMessage {
+            from: ActorInfos { server: origin, name: author },
+            to: ActorInfos { server: "telegram_server_id".to_owned(), name: Default::default() },
+            content,
+        },
+        sender_bot_family: BotFamily::Discord,
+        protocol_version: PROTOCOL_VERSION,
+    }
+}
+
+/// Команда пересылки сообщения Discord, прошедшего [`check_outgoing`]
+///
+/// Автор подписывается ником на сервере с именем
+/// пользователя в скобках, если ник задан. `content` -
+/// текст после [`super::transform::safe_content`]
+pub fn outgoing_command(
+    state: &BridgeState,
+    config: &BridgeConfig,
+    message_id: MessageId,
+    name: &str,
+    nickname: Option<String>,
+    content: &str,
+) -> Command {
+    let author = match nickname {
+        Some(nickname) => format!("{} ({})", nickname, name),
+        None => name.to_owned(),
+    };
+    let content = super::transform::discord_to_telegram(content, config);
+    forward_command(state.origin_for(message_id), author, content)
+}
+
+/// Причина, по которой сообщение Discord не пересылается
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Skipped {
+    /// Сообщение самого бота или вебхука моста
+    OwnMessage,
+    OtherChannel,
+    /// Автор - бот не из списка `allowed_bot_ids`
+    BotAuthor,
+    Paused,
+    RateLimited,
+}
+
+/// Сообщение Discord, предлагаемое для пересылки в мост
+#[derive(Debug, Clone)]
+pub struct Outgoing {
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    pub author_bot: bool,
+    /// Сообщение отправлено самим ботом или вебхуком моста
+    pub own: bool,
+}
+
+/// Проверка, нужно ли переслать сообщение в Telegram
+///
+/// Не обращается к Discord. Если проверка пройдена,
+/// расходуется токен ограничителя частоты
+pub fn check_outgoing(
+    msg: &Outgoing,
+    config: &BridgeConfig,
+    state: &BridgeState,
+    limiter: &BridgeLimiter,
+) -> Result<(), Skipped> {
+    if msg.own {
+        return Err(Skipped::OwnMessage);
+    }
+    if msg.channel_id != config.channel_id {
+        return Err(Skipped::OtherChannel);
+    }
+    if msg.author_bot && !config.allowed_bot_ids.contains(&msg.author_id.0) {
+        return Err(Skipped::BotAuthor);
+    }
+    if state.is_paused() {
+        return Err(Skipped::Paused);
+    }
+    if !limiter.try_acquire(&format!("ds:{}", msg.author_id.0), msg.channel_id) {
+        return Err(Skipped::RateLimited);
+    }
+    Ok(())
+}
+
+/// Отправка команды боту Telegram
+///
+/// Отправка выполняется в отдельном потоке, чтобы
+/// недоступный собеседник не блокировал обработку событий
+pub async fn send_to_telegram(ctx: &Context, cmd: Command) -> UResult {
+    let sender = {
+        let data = ctx.data.read().await;
+        data.get::<SendersKey>()
+            .and_then(|senders| senders.get("tgsender"))
+            .cloned()
+            .ok_or(BotError::DataNotFound("Telegram command sender"))?
+    };
+    tokio::task::spawn_blocking(move || sender.send(cmd))
+        .await
+        .map_err(|why| BotError::Internal(why.into()))?
+}
+
+/// Всё, что нужно для приёма команд из моста
+///
+/// Собирается заново для каждой команды, поэтому изменения
+/// конфигурации применяются без перезапуска
+#[derive(Clone)]
+pub struct Inbound {
+    pub config: BridgeConfig,
+    pub state: Arc<BridgeState>,
+    pub auth: Arc<BridgeAuth>,
+    pub limiter: Arc<BridgeLimiter>,
+    pub metrics: Arc<Metrics>,
+    pub discord: Arc<dyn DiscordOps>,
+}
+
+/// Источник зависимостей приёма команд (см. [`Inbound`])
+#[async_trait]
+pub trait InboundSource: Send + Sync {
+    async fn inbound(&self) -> UResult<Inbound>;
+}
+
+#[async_trait]
+impl InboundSource for Context {
+    async fn inbound(&self) -> UResult<Inbound> {
+        Ok(Inbound {
+            config: bot_config(self).await?.bridge,
+            state: bridge_state(self).await?,
+            auth: super::auth::bridge_auth(self).await?,
+            limiter: super::ratelimit::bridge_limiter(self).await?,
+            metrics: super::metrics::metrics(self).await?,
+            discord: Arc::new(SerenityOps::new(self)),
+        })
+    }
+}
+
+/// Неизменные зависимости, например собранные в тестах
+#[async_trait]
+impl InboundSource for Inbound {
+    async fn inbound(&self) -> UResult<Inbound> {
+        Ok(self.clone())
+    }
+}
+
+/// Приём команды из моста
+///
+/// Команды неподдерживаемых версий протокола отклоняются с
+/// ошибкой. Неподписанные и повторные команды, команды,
+/// вернувшиеся из Discord, и команды сверх ограничения
+/// частоты отбрасываются, остальные публикуются в канале
+/// моста
+pub async fn receive(inbound: &Inbound, logger: &Logger, msg: Command) -> UResult {
+    check_version(inbound, logger, &msg)?;
+    if !authenticate(inbound, logger, &msg) || inbound.state.is_paused() {
+        return Ok(());
+    }
+    let channel_id = ChannelId(inbound.config.channel_id);
+    match msg.kind {
+        CommandKind::ForwardMessage { from, to: _, content } => {
+            if inbound.state.has_traversed(&from.server) {
+                inbound.metrics.increment("bridge.loop_dropped");
+                return Ok(());
+            }
+            if !inbound.limiter.try_acquire(&format!("tg:{}", from.name), channel_id) {
+                inbound.metrics.increment("bridge.rate_limited");
+                return Ok(());
+            }
+            let content = super::transform::to_discord(&content, &inbound.config);
+            inbound.discord.post_bridged(channel_id, &from.name, &content).await
+        }
+        // Команды других видов отклоняет сервер команд
+        other => Err(BotError::Protocol(format!("Unsupported command: {:?}", other)).into()),
+    }
+}
+
+/// Проверка версии протокола входящей команды моста
+fn check_version(inbound: &Inbound, logger: &Logger, msg: &Command) -> UResult {
+    let version = super::protocol::command_version(msg);
+    if super::protocol::is_supported(version) {
+        return Ok(());
+    }
+    warn!(logger, "Rejected a bridge command with an unsupported protocol version";
+        "version" => format!("{:?}", version),
+        "supported" => format!("{:?}", super::protocol::supported_versions()),
+    );
+    inbound.metrics.increment("bridge.protocol.unsupported");
+    Err(BotError::Protocol(format!("Unsupported protocol version {:?}", version)).into())
+}
+
+/// Проверка подписи входящей команды моста
+///
+/// Неподписанные и отклонённые команды записываются в
+/// журнал аудита. Возвращает `false`, если команду
+/// нужно отбросить
+fn authenticate(inbound: &Inbound, logger: &Logger, msg: &Command) -> bool {
+    let (server, name) = match &msg.kind {
+        CommandKind::ForwardMessage { from, .. } => (from.server.as_str(), from.name.as_str()),
+        _ => ("", ""),
+    };
+    let audit = logger.new(o!("audit" => "bridge", "server" => server.to_owned(), "author" => name.to_owned()));
+    super::auth::admit(&inbound.metrics, &audit, inbound.auth.verify(msg))
+}
+
+pub struct BridgeStateKey;
+impl TypeMapKey for BridgeStateKey {
+    type Value = Arc<BridgeState>;
+}
+
+/// Получение текущего состояния моста
+pub async fn bridge_state(ctx: &Context) -> UResult<Arc<BridgeState>> {
+    let data = ctx.data.read().await;
+    data.get::<BridgeStateKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Bridge state").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_eligible_discord_messages_are_forwarded() {
+        let config = BridgeConfig {
+            channel_id: 10,
+            allowed_bot_ids: vec![7],
+            ..Default::default()
+        };
+        let state = BridgeState::default();
+        let limiter = BridgeLimiter::new(&BridgeLimitsConfig {
+            user: TokenBucketConfig {
+                capacity: 1,
+                refill_per_minute: 0,
+            },
+            channel: TokenBucketConfig {
+                capacity: 10,
+                refill_per_minute: 0,
+            },
+        });
+        let check = |channel: u64, author: u64, author_bot: bool, own: bool| {
+            let msg = Outgoing {
+                channel_id: ChannelId(channel),
+                author_id: UserId(author),
+                author_bot,
+                own,
+            };
+            check_outgoing(&msg, &config, &state, &limiter)
+        };
+
+        assert_eq!(check(10, 1, false, true), Err(Skipped::OwnMessage));
+        assert_eq!(check(11, 1, false, false), Err(Skipped::OtherChannel));
+        assert_eq!(check(10, 8, true, false), Err(Skipped::BotAuthor));
+        assert_eq!(check(10, 7, true, false), Ok(()));
+        assert_eq!(check(10, 1, false, false), Ok(()));
+        assert_eq!(check(10, 1, false, false), Err(Skipped::RateLimited));
+        state.pause();
+        assert_eq!(check(10, 2, false, false), Err(Skipped::Paused));
+    }
+}
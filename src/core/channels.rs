@@ -0,0 +1,76 @@
+use crate::prelude::*;
+use serenity::{http::StatusCode, model::prelude::*, prelude::*};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Время жизни записи в кэше каналов
+const CHANNEL_TTL: Duration = Duration::from_secs(60);
+
+/// Небольшой кэш каналов с ограниченным временем жизни
+///
+/// Дополняет кэш Serenity для каналов, которые в нём
+/// отсутствуют и были запрошены через HTTP
+#[derive(Debug, Default)]
+pub struct ChannelCache {
+    channels: RwLock<HashMap<ChannelId, (GuildChannel, Instant)>>,
+}
+
+impl ChannelCache {
+    pub fn get(&self, channel_id: ChannelId) -> Option<GuildChannel> {
+        self.channels.read().ok().and_then(|channels| {
+            channels
+                .get(&channel_id)
+                .filter(|(_, cached_at)| cached_at.elapsed() < CHANNEL_TTL)
+                .map(|(channel, _)| channel.clone())
+        })
+    }
+
+    pub fn insert(&self, channel: GuildChannel) {
+        if let Ok(mut channels) = self.channels.write() {
+            channels.retain(|_, (_, cached_at)| cached_at.elapsed() < CHANNEL_TTL);
+            channels.insert(channel.id, (channel, Instant::now()));
+        }
+    }
+}
+
+pub struct ChannelsKey;
+impl TypeMapKey for ChannelsKey {
+    type Value = Arc<ChannelCache>;
+}
+
+/// Получение кэша каналов
+pub async fn channel_cache(ctx: &Context) -> UResult<Arc<ChannelCache>> {
+    let data = ctx.data.read().await;
+    data.get::<ChannelsKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Channel cache").into())
+}
+
+/// Получение канала группы по идентификатору
+///
+/// Канал ищется в кэше Serenity, затем в собственном кэше
+/// и только после этого запрашивается у Discord. Если канал
+/// удалён или не является каналом группы, возвращается
+/// ошибка конфигурации
+pub async fn resolve_channel(ctx: &Context, channel_id: ChannelId) -> UResult<GuildChannel> {
+    if let Some(channel) = ctx.cache.guild_channel(channel_id) {
+        return Ok(channel);
+    }
+    let cache = channel_cache(ctx).await?;
+    if let Some(channel) = cache.get(channel_id) {
+        return Ok(channel);
+    }
+
+    match ctx.http.get_channel(channel_id.0).await {
+        Ok(Channel::Guild(channel)) => {
+            cache.insert(channel.clone());
+            Ok(channel)
+        }
+        Ok(_) => Err(BotError::Config(format!("Channel {} is not a guild channel", channel_id)).into()),
+        Err(SerenityError::Http(why)) if why.status_code() == Some(StatusCode::NOT_FOUND) => {
+            Err(BotError::Config(format!("Channel {} no longer exists", channel_id)).into())
+        }
+        Err(why) => Err(why.into()),
+    }
+}
@@ -0,0 +1,217 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::{model::prelude::*, utils::MessageBuilder};
+use std::time::Duration;
+
+use super::discord::DiscordOps;
+
+/// Ключевые слова, которыми пользователь может прервать диалог
+pub const CANCEL_KEYWORDS: &[&str] = &["/cancel", "!cancel", "отмена"];
+
+/// Ответы, считающиеся согласием
+const CONFIRM_YES: &[&str] = &["да", "+", "ок", "yes", "y"];
+/// Ответы, считающиеся отказом
+const CONFIRM_NO: &[&str] = &["нет", "no", "-", "n"];
+
+/// Канал общения бота с пользователем
+///
+/// Регистрация проводится в личных сообщениях, а если они
+/// закрыты - в приватной ветке канала знакомства
+/// (`signup.onboarding_channel_id`). Оба варианта используются
+/// одинаково, поэтому процесс регистрации не зависит от того,
+/// где именно он проходит.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ConversationChannel {
+    /// Личные сообщения пользователя
+    Direct(ChannelId),
+    /// Приватная ветка в канале знакомства
+    Thread(ChannelId),
+}
+
+impl ConversationChannel {
+    /// Открытие канала общения с первым сообщением
+    ///
+    /// Сообщение сначала отправляется в личные сообщения. Если
+    /// пользователь их закрыл, а канал знакомства настроен
+    /// (`onboarding_channel_id` не равен нулю), для него
+    /// создаётся приватная ветка и сообщение отправляется туда
+    pub async fn open(
+        ops: &dyn DiscordOps,
+        user: &User,
+        gid: &GuildId,
+        onboarding_channel_id: u64,
+        msg: &str,
+    ) -> UResult<Self> {
+        let direct = Self::Direct(ops.open_direct(user).await?);
+        let why = match direct.say(ops, msg).await {
+            Ok(_) => return Ok(direct),
+            Err(why) => BotError::from(why),
+        };
+        if !why.is_dm_closed() || onboarding_channel_id == 0 {
+            return Err(why.into());
+        }
+
+        let thread = ops
+            .open_thread(ChannelId(onboarding_channel_id), *gid, user)
+            .await
+            .context("Opening a private onboarding thread")?;
+        let thread = Self::Thread(thread);
+        thread.say(ops, msg).await?;
+        Ok(thread)
+    }
+
+    /// Идентификатор канала Discord
+    pub fn id(&self) -> ChannelId {
+        match self {
+            Self::Direct(id) | Self::Thread(id) => *id,
+        }
+    }
+
+    /// Отправка сообщения пользователю
+    pub async fn say(&self, ops: &dyn DiscordOps, msg: &str) -> UResult {
+        ops.send_message(self.id(), msg).await
+    }
+
+    /// Завершение общения
+    ///
+    /// Приватная ветка архивируется и закрывается, личные
+    /// сообщения остаются как есть
+    pub async fn close(&self, ops: &dyn DiscordOps) -> UResult {
+        match self {
+            Self::Thread(id) => ops.close_thread(*id).await,
+            Self::Direct(_) => Ok(()),
+        }
+    }
+}
+
+/// Диалог бота с конкретным пользователем
+///
+/// Диалог привязан к каналу общения и пользователю: учитываются
+/// только ответы этого пользователя в этом канале. Каждый запрос
+/// ожидает ответа не дольше заданного времени, по истечении
+/// которого возвращается `BotError::TimedOut`. Ответ из
+/// [`CANCEL_KEYWORDS`] прерывает диалог с ошибкой
+/// `UserInputError::Cancelled`.
+#[derive(Debug, Clone, Copy)]
+pub struct Conversation {
+    channel: ConversationChannel,
+    user_id: UserId,
+    timeout: Duration,
+}
+
+impl Conversation {
+    pub fn new(channel: ConversationChannel, user_id: UserId, timeout: Duration) -> Self {
+        Self {
+            channel,
+            user_id,
+            timeout,
+        }
+    }
+
+    /// Открытие диалога с первым сообщением
+    ///
+    /// См. [`ConversationChannel::open`]
+    pub async fn open(
+        ops: &dyn DiscordOps,
+        user: &User,
+        gid: &GuildId,
+        onboarding_channel_id: u64,
+        msg: &str,
+        timeout: Duration,
+    ) -> UResult<Self> {
+        let channel = ConversationChannel::open(ops, user, gid, onboarding_channel_id, msg).await?;
+        Ok(Self::new(channel, user.id, timeout))
+    }
+
+    /// Копия диалога с другим временем ожидания ответа
+    ///
+    /// Используется для отдельных запросов, которым нужно
+    /// больше или меньше времени, чем по умолчанию
+    pub fn with_timeout(&self, timeout: Duration) -> Self {
+        Self { timeout, ..*self }
+    }
+
+    pub fn channel(&self) -> &ConversationChannel {
+        &self.channel
+    }
+
+    pub fn user_id(&self) -> UserId {
+        self.user_id
+    }
+
+    /// Отправка сообщения пользователю
+    pub async fn say(&self, ops: &dyn DiscordOps, msg: &str) -> UResult {
+        self.channel.say(ops, msg).await
+    }
+
+    /// Запрос произвольного текста
+    pub async fn ask_text(&self, ops: &dyn DiscordOps, prompt: &str) -> UResult<String> {
+        self.say(ops, prompt).await?;
+
+        let reply = ops
+            .await_reply(self.channel.id(), self.user_id, self.timeout)
+            .await
+            .map(|content| content.trim().to_owned())
+            .ok_or(BotError::TimedOut)?;
+
+        if CANCEL_KEYWORDS.contains(&reply.to_lowercase().as_str()) {
+            return Err(BotError::UserInput(UserInputError::Cancelled).into());
+        }
+        Ok(reply)
+    }
+
+    /// Запрос выбора одного из вариантов
+    ///
+    /// Вариант можно указать номером или названием. Запрос
+    /// повторяется, пока не будет получен корректный ответ
+    pub async fn ask_choice(&self, ops: &dyn DiscordOps, prompt: &str, options: &[String]) -> UResult<String> {
+        let mut msg = MessageBuilder::new();
+        msg.push_bold_line_safe(prompt);
+        for (i, option) in options.iter().enumerate() {
+            msg.push_line_safe(format!("{}. {}", i + 1, option));
+        }
+        let msg = msg.build();
+
+        loop {
+            let reply = self.ask_text(ops, &msg).await?;
+            match match_choice(options, &reply) {
+                Some(option) => return Ok(option.clone()),
+                None => self.say(ops, "Выберите один из предложенных вариантов").await?,
+            }
+        }
+    }
+
+    /// Запрос подтверждения (Да/Нет)
+    ///
+    /// Запрос повторяется, пока не будет получен корректный ответ
+    pub async fn ask_confirm(&self, ops: &dyn DiscordOps, prompt: &str) -> UResult<bool> {
+        let msg = MessageBuilder::new().push_bold_line_safe(prompt).build();
+
+        loop {
+            let reply = self.ask_text(ops, &msg).await?.to_lowercase();
+            if CONFIRM_YES.contains(&reply.as_str()) {
+                return Ok(true);
+            }
+            if CONFIRM_NO.contains(&reply.as_str()) {
+                return Ok(false);
+            }
+            self.say(ops, "Вы можете ответить только 'Да' или 'Нет'").await?;
+        }
+    }
+
+    /// Завершение диалога
+    pub async fn close(&self, ops: &dyn DiscordOps) -> UResult {
+        self.channel.close(ops).await
+    }
+}
+
+/// Поиск варианта по номеру (начиная с единицы) или названию
+pub fn match_choice<'a>(options: &'a [String], raw: &str) -> Option<&'a String> {
+    let raw = raw.trim();
+    let by_index = raw
+        .parse::<usize>()
+        .ok()
+        .and_then(|i| i.checked_sub(1))
+        .and_then(|i| options.get(i));
+    by_index.or_else(|| options.iter().find(|o| o.to_lowercase() == raw.to_lowercase()))
+}
@@ -0,0 +1,199 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::{model::prelude::*, prelude::*};
+use std::collections::HashMap;
+
+use super::storage::MemberRecord;
+
+/// Максимальное число участников в ответе справочника
+pub const MAX_DIRECTORY_RESULTS: usize = 25;
+
+/// Запись справочника участников
+///
+/// `game_nickname` - игровой ник, указанный при регистрации,
+/// `status` - статус присутствия в Discord, если он известен
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MemberInfo {
+    pub user_id: u64,
+    pub user_name: String,
+    pub display_name: String,
+    pub game_nickname: Option<String>,
+    pub status: Option<String>,
+}
+
+impl MemberInfo {
+    pub fn is_online(&self) -> bool {
+        matches!(self.status.as_deref(), Some(status) if status != OnlineStatus::Offline.name() && status != OnlineStatus::Invisible.name())
+    }
+}
+
+/// Участник группы из кэша Discord
+#[derive(Debug, Clone)]
+pub struct CachedMember {
+    pub user_id: u64,
+    pub user_name: String,
+    pub display_name: String,
+    pub status: Option<String>,
+}
+
+/// Объединение участников из кэша с записями о регистрации
+///
+/// Если кэш группы недоступен (`cached` равен `None`),
+/// справочник строится только по записям о регистрации
+pub fn merge(cached: Option<Vec<CachedMember>>, records: Vec<MemberRecord>) -> Vec<MemberInfo> {
+    let mut nicknames: HashMap<u64, MemberRecord> = records.into_iter().map(|r| (r.user_id, r)).collect();
+    let mut entries: Vec<MemberInfo> = match cached {
+        Some(members) => members
+            .into_iter()
+            .map(|member| MemberInfo {
+                game_nickname: nicknames.remove(&member.user_id).map(|r| r.nickname),
+                user_id: member.user_id,
+                user_name: member.user_name,
+                display_name: member.display_name,
+                status: member.status,
+            })
+            .collect(),
+        None => nicknames
+            .into_values()
+            .map(|record| MemberInfo {
+                user_id: record.user_id,
+                display_name: record.user_name.clone(),
+                user_name: record.user_name,
+                game_nickname: Some(record.nickname),
+                status: None,
+            })
+            .collect(),
+    };
+    entries.sort_by_key(|entry| entry.display_name.to_lowercase());
+    entries
+}
+
+/// Поиск участников по игровому нику или имени в Discord
+///
+/// Сначала идут точные совпадения игрового ника, затем
+/// частичные совпадения
+pub fn search(entries: Vec<MemberInfo>, query: &str) -> Vec<MemberInfo> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let mut found: Vec<(bool, MemberInfo)> = entries
+        .into_iter()
+        .filter_map(|entry| {
+            let nickname = entry.game_nickname.as_deref().unwrap_or_default().to_lowercase();
+            let exact = nickname == query;
+            let partial = nickname.contains(&query)
+                || entry.display_name.to_lowercase().contains(&query)
+                || entry.user_name.to_lowercase().contains(&query);
+            (exact || partial).then_some((exact, entry))
+        })
+        .collect();
+    found.sort_by_key(|(exact, _)| !exact);
+    found
+        .into_iter()
+        .map(|(_, entry)| entry)
+        .take(MAX_DIRECTORY_RESULTS)
+        .collect()
+}
+
+/// Участники в сети
+pub fn online(entries: Vec<MemberInfo>) -> Vec<MemberInfo> {
+    entries
+        .into_iter()
+        .filter(MemberInfo::is_online)
+        .take(MAX_DIRECTORY_RESULTS)
+        .collect()
+}
+
+/// Участники группы из кэша Discord, без ботов
+fn cached_members(ctx: &Context, gid: GuildId) -> Option<Vec<CachedMember>> {
+    let guild = ctx.cache.guild(gid)?;
+    let members = guild
+        .members
+        .values()
+        .filter(|member| !member.user.bot)
+        .map(|member| CachedMember {
+            user_id: member.user.id.0,
+            user_name: member.user.name.clone(),
+            display_name: member.display_name().into_owned(),
+            status: guild
+                .presences
+                .get(&member.user.id)
+                .map(|presence| presence.status.name().to_owned()),
+        })
+        .collect();
+    Some(members)
+}
+
+/// Справочник участников группы
+pub async fn directory(ctx: &Context) -> UResult<Vec<MemberInfo>> {
+    let config = bot_config(ctx).await?;
+    let gid = config.general.guild_id;
+    let records = super::storage::member_storage(ctx)
+        .await?
+        .all()?
+        .into_iter()
+        .filter(|r| r.guild_id == gid)
+        .collect();
+    Ok(merge(cached_members(ctx, GuildId(gid)), records))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(user_id: u64, nickname: &str) -> MemberRecord {
+        MemberRecord {
+            guild_id: 100,
+            user_id,
+            user_name: format!("user{}", user_id),
+            nickname: nickname.to_owned(),
+            answers: Vec::new(),
+            registered_at: String::new(),
+        }
+    }
+
+    fn cached(user_id: u64, display_name: &str, status: Option<&str>) -> CachedMember {
+        CachedMember {
+            user_id,
+            user_name: format!("user{}", user_id),
+            display_name: display_name.to_owned(),
+            status: status.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn directory_merges_cache_with_signup_nicknames() {
+        let members = vec![
+            cached(1, "Бета", Some("online")),
+            cached(2, "Альфа", Some("offline")),
+            cached(3, "Гамма", None),
+        ];
+        let records = vec![record(1, "Innri"), record(4, "Ушедший")];
+        let entries = merge(Some(members), records.clone());
+
+        let names: Vec<_> = entries.iter().map(|e| e.display_name.as_str()).collect();
+        assert_eq!(names, vec!["Альфа", "Бета", "Гамма"]);
+        assert_eq!(entries[1].game_nickname.as_deref(), Some("Innri"));
+
+        let online = online(entries);
+        assert_eq!(online.len(), 1);
+        assert_eq!(online[0].user_id, 1);
+
+        let offline_cache = merge(None, records);
+        assert_eq!(offline_cache.len(), 2);
+        assert!(offline_cache.iter().all(|e| e.status.is_none()));
+    }
+
+    #[test]
+    fn directory_search_prefers_exact_game_nicknames() {
+        let members = vec![cached(1, "Innri Fan", None), cached(2, "Кто-то", None)];
+        let entries = merge(Some(members), vec![record(2, "innri")]);
+
+        let found = search(entries.clone(), "Innri");
+        let ids: Vec<_> = found.iter().map(|e| e.user_id).collect();
+        assert_eq!(ids, vec![2, 1]);
+
+        assert!(search(entries, "  ").is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::prelude::*;
+use serenity::{async_trait, model::prelude::*, prelude::*, utils::MessageBuilder};
+use std::time::Duration;
+
+use super::approval::Application;
+
+/// Операции с Discord, используемые регистрацией и мостом
+///
+/// Процесс регистрации и приём команд моста обращаются к
+/// Discord только через эту черту, поэтому их можно
+/// проверить без подключения к Discord (см. `FakeDiscord`
+/// в тестах)
+#[async_trait]
+pub trait DiscordOps: Send + Sync {
+    /// Открытие личных сообщений с пользователем
+    async fn open_direct(&self, user: &User) -> UResult<ChannelId>;
+
+    /// Создание приватной ветки для пользователя в канале
+    async fn open_thread(&self, parent: ChannelId, gid: GuildId, user: &User) -> UResult<ChannelId>;
+
+    /// Архивация и закрытие ветки
+    async fn close_thread(&self, thread: ChannelId) -> UResult;
+
+    async fn send_message(&self, channel: ChannelId, content: &str) -> UResult;
+
+    /// Ожидание ответа пользователя в канале
+    ///
+    /// Возвращает `None`, если пользователь не ответил
+    /// за отведённое время
+    async fn await_reply(&self, channel: ChannelId, user: UserId, timeout: Duration) -> Option<String>;
+
+    async fn add_role(&self, gid: GuildId, uid: UserId, role: RoleId, reason: &str) -> UResult;
+
+    async fn remove_role(&self, gid: GuildId, uid: UserId, role: RoleId, reason: &str) -> UResult;
+
+    async fn set_nickname(&self, gid: GuildId, uid: UserId, nickname: &str) -> UResult;
+
+    async fn check_membership(&self, uid: UserId, gid: GuildId) -> Membership;
+
+    /// Публикация сообщения из моста от имени удалённого автора
+    ///
+    /// Упоминания в сообщении не срабатывают
+    async fn post_bridged(&self, channel: ChannelId, author: &str, content: &str) -> UResult;
+
+    /// Публикация заявки на вступление в канале администрации
+    async fn post_application(&self, channel: ChannelId, application: &Application) -> UResult;
+}
+
+/// Реализация операций через Serenity
+#[derive(Clone)]
+pub struct SerenityOps {
+    ctx: Context,
+}
+
+impl SerenityOps {
+    pub fn new(ctx: &Context) -> Self {
+        Self { ctx: ctx.clone() }
+    }
+}
+
+#[async_trait]
+impl DiscordOps for SerenityOps {
+    async fn open_direct(&self, user: &User) -> UResult<ChannelId> {
+        Ok(user.create_dm_channel(&self.ctx.http).await?.id)
+    }
+
+    async fn open_thread(&self, parent: ChannelId, gid: GuildId, user: &User) -> UResult<ChannelId> {
+        let nick = user.nick_in(&self.ctx, gid).await;
+        let name = format!("Регистрация {}", nick.as_deref().unwrap_or(&user.name));
+        let thread = parent
+            .create_private_thread(&self.ctx.http, |t| t.name(name).kind(ChannelType::PrivateThread))
+            .await?;
+        thread.id.add_thread_member(&self.ctx.http, user.id).await?;
+        thread.id.say(&self.ctx.http, user.mention().to_string()).await?;
+        Ok(thread.id)
+    }
+
+    async fn close_thread(&self, thread: ChannelId) -> UResult {
+        thread
+            .edit_thread(&self.ctx.http, |t| t.archived(true).locked(true))
+            .await?;
+        Ok(())
+    }
+
+    async fn send_message(&self, channel: ChannelId, content: &str) -> UResult {
+        channel.send_message(&self.ctx.http, |m| m.content(content)).await?;
+        Ok(())
+    }
+
+    async fn await_reply(&self, channel: ChannelId, user: UserId, timeout: Duration) -> Option<String> {
+        channel
+            .await_reply(&self.ctx.shard)
+            .author_id(user)
+            .timeout(timeout)
+            .await
+            .map(|m| m.content.clone())
+    }
+
+    async fn add_role(&self, gid: GuildId, uid: UserId, role: RoleId, reason: &str) -> UResult {
+        self.ctx
+            .http
+            .add_member_role(gid.0, uid.0, role.0, Some(reason))
+            .await?;
+        Ok(())
+    }
+
+    async fn remove_role(&self, gid: GuildId, uid: UserId, role: RoleId, reason: &str) -> UResult {
+        self.ctx
+            .http
+            .remove_member_role(gid.0, uid.0, role.0, Some(reason))
+            .await?;
+        Ok(())
+    }
+
+    async fn set_nickname(&self, gid: GuildId, uid: UserId, nickname: &str) -> UResult {
+        gid.edit_member(&self.ctx.http, uid, |member| member.nickname(nickname))
+            .await?;
+        Ok(())
+    }
+
+    async fn check_membership(&self, uid: UserId, gid: GuildId) -> Membership {
+        check_membership(&self.ctx, uid, &gid).await
+    }
+
+    async fn post_bridged(&self, channel: ChannelId, author: &str, content: &str) -> UResult {
+        let channel = super::channels::resolve_channel(&self.ctx, channel)
+            .await
+            .context("Resolving the bridge channel")?;
+        // ActorInfos не содержит аватара отправителя, поэтому используется аватар вебхука
+        if super::webhooks::post_as(&self.ctx, channel.id, author, None, content).await? {
+            return Ok(());
+        }
+        let content = MessageBuilder::new()
+            .push_bold_safe(author)
+            .push_line_safe(" пишет:")
+            .push(content)
+            .build();
+        channel
+            .send_message(&self.ctx.http, |m| m.content(content).allowed_mentions(|am| am.empty_parse()))
+            .await?;
+        Ok(())
+    }
+
+    async fn post_application(&self, channel: ChannelId, application: &Application) -> UResult {
+        super::approval::post_application(&self.ctx, channel, application).await
+    }
+}
+
+#[cfg(test)]
+pub mod fake {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::sync::{Mutex, MutexGuard};
+
+    /// Идентификатор личных сообщений в `FakeDiscord`
+    pub const FAKE_DM: ChannelId = ChannelId(1);
+    /// Идентификатор ветки, создаваемой `FakeDiscord`
+    pub const FAKE_THREAD: ChannelId = ChannelId(2);
+
+    /// Состояние поддельного Discord
+    #[derive(Debug, Default)]
+    pub struct FakeState {
+        /// Ответы пользователя по порядку, `None` - молчание до таймаута
+        pub replies: VecDeque<Option<String>>,
+        pub sent: Vec<(ChannelId, String)>,
+        pub added_roles: Vec<RoleId>,
+        pub removed_roles: Vec<RoleId>,
+        pub nickname: Option<String>,
+        pub closed_threads: Vec<ChannelId>,
+        pub dm_closed: bool,
+        /// Число проверок членства, после которого пользователь покидает группу
+        pub leaves_after: Option<usize>,
+        pub membership_checks: usize,
+        /// Сообщения из моста: канал, автор и текст
+        pub bridged: Vec<(ChannelId, String, String)>,
+        /// Заявки на вступление: канал и ник в игре
+        pub applications: Vec<(ChannelId, String)>,
+    }
+
+    /// Поддельный Discord с заранее заданными ответами пользователя
+    #[derive(Debug, Default)]
+    pub struct FakeDiscord {
+        state: Mutex<FakeState>,
+    }
+
+    impl FakeDiscord {
+        pub fn with_replies<'a, I: IntoIterator<Item = &'a str>>(replies: I) -> Self {
+            let fake = Self::default();
+            fake.state().replies = replies.into_iter().map(|r| Some(r.to_owned())).collect();
+            fake
+        }
+
+        /// Добавление паузы, после которой истекает время ожидания ответа
+        pub fn then_silence(self) -> Self {
+            self.state().replies.push_back(None);
+            self
+        }
+
+        pub fn state(&self) -> MutexGuard<'_, FakeState> {
+            self.state.lock().expect("fake state lock")
+        }
+
+        /// Все сообщения, отправленные пользователю
+        pub fn sent_texts(&self) -> Vec<String> {
+            self.state().sent.iter().map(|(_, text)| text.clone()).collect()
+        }
+    }
+
+    #[async_trait]
+    impl DiscordOps for FakeDiscord {
+        async fn open_direct(&self, _user: &User) -> UResult<ChannelId> {
+            Ok(FAKE_DM)
+        }
+
+        async fn open_thread(&self, _parent: ChannelId, _gid: GuildId, _user: &User) -> UResult<ChannelId> {
+            Ok(FAKE_THREAD)
+        }
+
+        async fn close_thread(&self, thread: ChannelId) -> UResult {
+            self.state().closed_threads.push(thread);
+            Ok(())
+        }
+
+        async fn send_message(&self, channel: ChannelId, content: &str) -> UResult {
+            let mut state = self.state();
+            if channel == FAKE_DM && state.dm_closed {
+                return Err(BotError::UserInput(UserInputError::DirectMessagesClosed).into());
+            }
+            state.sent.push((channel, content.to_owned()));
+            Ok(())
+        }
+
+        async fn await_reply(&self, _channel: ChannelId, _user: UserId, _timeout: Duration) -> Option<String> {
+            self.state().replies.pop_front().flatten()
+        }
+
+        async fn add_role(&self, _gid: GuildId, _uid: UserId, role: RoleId, _reason: &str) -> UResult {
+            self.state().added_roles.push(role);
+            Ok(())
+        }
+
+        async fn remove_role(&self, _gid: GuildId, _uid: UserId, role: RoleId, _reason: &str) -> UResult {
+            self.state().removed_roles.push(role);
+            Ok(())
+        }
+
+        async fn set_nickname(&self, _gid: GuildId, _uid: UserId, nickname: &str) -> UResult {
+            self.state().nickname = Some(nickname.to_owned());
+            Ok(())
+        }
+
+        async fn check_membership(&self, _uid: UserId, _gid: GuildId) -> Membership {
+            let mut state = self.state();
+            state.membership_checks += 1;
+            match state.leaves_after {
+                Some(limit) if state.membership_checks > limit => Membership::NotMember,
+                _ => Membership::Present,
+            }
+        }
+
+        async fn post_bridged(&self, channel: ChannelId, author: &str, content: &str) -> UResult {
+            self.state().bridged.push((channel, author.to_owned(), content.to_owned()));
+            Ok(())
+        }
+
+        async fn post_application(&self, channel: ChannelId, application: &Application) -> UResult {
+            self.state().applications.push((channel, application.nickname.clone()));
+            Ok(())
+        }
+    }
+}
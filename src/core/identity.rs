@@ -0,0 +1,47 @@
+use crate::prelude::*;
+use serenity::{model::prelude::*, prelude::*};
+use std::sync::Arc;
+
+/// Сведения о текущем пользователе бота
+///
+/// Заполняются при событии `Ready`, поэтому бот корректно
+/// распознаёт себя при запуске с любым токеном
+#[derive(Debug, Clone)]
+pub struct BotIdentity {
+    pub user_id: UserId,
+    pub user_name: String,
+    pub application_id: ApplicationId,
+    pub shard_id: u64,
+    pub shard_count: u64,
+}
+
+impl BotIdentity {
+    pub fn from_ready(ready: &Ready) -> Self {
+        let [shard_id, shard_count] = ready.shard.unwrap_or([0, 1]);
+        Self {
+            user_id: ready.user.id,
+            user_name: ready.user.name.clone(),
+            application_id: ready.application.id,
+            shard_id,
+            shard_count,
+        }
+    }
+
+    /// Признак того, что пользователь является самим ботом
+    pub fn is_self(&self, user_id: UserId) -> bool {
+        self.user_id == user_id
+    }
+}
+
+pub struct BotIdentityKey;
+impl TypeMapKey for BotIdentityKey {
+    type Value = Arc<BotIdentity>;
+}
+
+/// Получение сведений о текущем пользователе бота
+pub async fn bot_identity(ctx: &Context) -> UResult<Arc<BotIdentity>> {
+    let data = ctx.data.read().await;
+    data.get::<BotIdentityKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Bot identity").into())
+}
@@ -0,0 +1,48 @@
+use crate::prelude::*;
+use serenity::prelude::*;
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+/// Простые счётчики событий бота
+///
+/// Счётчики именуются через точку, например `errors.discord`
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: RwLock<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    pub fn increment(&self, name: &str) {
+        if let Ok(mut counters) = self.counters.write() {
+            *counters.entry(name.to_owned()).or_insert(0) += 1;
+        }
+    }
+
+    pub fn get(&self, name: &str) -> u64 {
+        self.counters
+            .read()
+            .map(|counters| counters.get(name).copied().unwrap_or_default())
+            .unwrap_or_default()
+    }
+
+    /// Текущие значения всех счётчиков
+    pub fn snapshot(&self) -> Vec<(String, u64)> {
+        self.counters
+            .read()
+            .map(|counters| counters.iter().map(|(k, v)| (k.clone(), *v)).collect())
+            .unwrap_or_default()
+    }
+}
+
+pub struct MetricsKey;
+impl TypeMapKey for MetricsKey {
+    type Value = Arc<Metrics>;
+}
+
+/// Получение счётчиков событий бота
+pub async fn metrics(ctx: &Context) -> UResult<Arc<Metrics>> {
+    let data = ctx.data.read().await;
+    data.get::<MetricsKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Metrics").into())
+}
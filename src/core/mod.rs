@@ -1,14 +1,61 @@
+pub mod announcements;
 pub mod application;
+pub mod approval;
+pub mod auth;
+pub mod bridge;
+pub mod channels;
+pub mod conversation;
+pub mod directory;
+pub mod discord;
+pub mod identity;
+pub mod metrics;
+pub mod protocol;
+pub mod questionnaire;
+pub mod ratelimit;
+pub mod reporter;
+pub mod requests;
+pub mod roles;
+pub mod sessions;
+pub mod socket;
+pub mod storage;
+pub mod transform;
+pub mod transport;
+pub mod webhooks;
+
+#[cfg(test)]
+mod peer;
+#[cfg(test)]
+mod tests;
 
 use std::{
     fs::File,
     io::{BufReader, Read},
     path::Path,
+    sync::Arc,
+    time::Duration,
 };
 
 use serenity::{model::prelude::*, prelude::*, utils::MessageBuilder};
 
 use crate::prelude::*;
+use conversation::Conversation;
+use discord::{DiscordOps, SerenityOps};
+
+/// Максимальная длина ника участника (ограничение Discord)
+const MAX_NICKNAME_LENGTH: usize = 32;
+
+/// Проверка ника перед установкой псевдонима
+///
+/// Возвращает ник без пробелов по краям или сообщение для
+/// пользователя с описанием ошибки
+pub fn validate_nickname(raw: &str) -> Result<String, String> {
+    let nickname = raw.trim();
+    if (1..=MAX_NICKNAME_LENGTH).contains(&nickname.chars().count()) {
+        Ok(nickname.to_owned())
+    } else {
+        Err(format!("Ник должен содержать от 1 до {} символов", MAX_NICKNAME_LENGTH))
+    }
+}
 
 /// Загрузка текста с правилами гильдии из предусмотренного файла
 ///
@@ -17,98 +64,293 @@ use crate::prelude::*;
 /// размеру не более 2000 символов (Ограничение Discord)
 fn load_guild_rules() -> UResult<Vec<String>> {
     let path = Path::new("rules.md");
-    let file = File::open(path)?;
+    let file = File::open(path)
+        .map_err(|why| BotError::Config(format!("Could not open the rules file: {}", why)))?;
     let mut reader = BufReader::new(file);
     let mut buffer = String::new();
-    reader.read_to_string(&mut buffer)?;
-    let content: Result<Vec<_>, _> = buffer
+    reader
+        .read_to_string(&mut buffer)
+        .map_err(|why| BotError::Config(format!("Could not read the rules file: {}", why)))?;
+    buffer
         .split_terminator("===")
         .map(|paragraph| {
             if paragraph.len() > 2000 {
-                Err(BotError::MessageTooLong)
+                Err(BotError::Config(format!(
+                    "Rules paragraph is bigger than Discord allows ({} > 2000)",
+                    paragraph.len()
+                ))
+                .into())
             } else {
                 Ok(String::from(paragraph))
             }
         })
-        .collect();
-    match content {
-        Ok(v) => Ok(v),
-        Err(why) => Err(why.into()),
+        .collect()
+}
+
+/// Итог диалога регистрации
+#[derive(Debug)]
+pub enum SignupOutcome {
+    /// Пользователь не принял правила гильдии
+    Declined,
+    /// Пользователь покинул группу во время регистрации
+    Left,
+    /// Сессия была прервана извне
+    Cancelled,
+    /// Пользователь ответил на все вопросы
+    Completed(approval::Application),
+}
+
+/// Зависимости процесса регистрации
+///
+/// Контекст Discord нужен только для их сборки (см.
+/// [`SignupDeps`]), поэтому регистрацию можно
+/// провести целиком с `FakeDiscord`
+pub struct SignupEnv<'a> {
+    pub ops: &'a dyn DiscordOps,
+    pub config: &'a BotConfig,
+    pub sessions: &'a Arc<sessions::SessionRegistry>,
+    pub approvals: &'a approval::ApprovalRegistry,
+    pub storage: &'a storage::MemberStorage,
+}
+
+/// Зависимости регистрации, собранные из контекста Discord
+pub struct SignupDeps {
+    ops: SerenityOps,
+    config: BotConfig,
+    sessions: Arc<sessions::SessionRegistry>,
+    approvals: Arc<approval::ApprovalRegistry>,
+    storage: Arc<storage::MemberStorage>,
+}
+
+impl SignupDeps {
+    pub async fn from_context(ctx: &Context) -> UResult<Self> {
+        Ok(Self {
+            ops: SerenityOps::new(ctx),
+            config: bot_config(ctx).await?,
+            sessions: sessions::session_registry(ctx).await?,
+            approvals: approval::approval_registry(ctx).await?,
+            storage: storage::member_storage(ctx).await?,
+        })
+    }
+
+    pub fn env(&self) -> SignupEnv<'_> {
+        SignupEnv {
+            ops: &self.ops,
+            config: &self.config,
+            sessions: &self.sessions,
+            approvals: &self.approvals,
+            storage: &self.storage,
+        }
     }
 }
 
+/// Правила гильдии из файла `rules.md`
+pub fn guild_rules() -> UResult<Vec<String>> {
+    Ok(load_guild_rules().context("Loading the guild rules")?)
+}
+
+/// Проведение регистрации пользователя в группе гильдии
+///
+/// Собирает зависимости из контекста и правила гильдии из
+/// файла, после чего проводит [`signup_session`]
 pub async fn start_signup_session(ctx: &Context, user: &User, gid: &GuildId) -> UResult {
-    let rules = load_guild_rules()?;
+    let deps = SignupDeps::from_context(ctx).await?;
+    signup_session(&deps.env(), user, gid, guild_rules()?).await
+}
 
-    for paragraph in rules {
-        let msg = MessageBuilder::new().push(paragraph).build();
-        send_privately(ctx, user, &msg).await?;
+/// Регистрация по команде `rules`
+///
+/// Сообщает в канале команды о начале регистрации и
+/// проводит [`signup_session`] с правилами `rules` (или
+/// ошибкой их загрузки). О неудаче пользователь узнаёт в
+/// личных сообщениях, а если они закрыты - в канале команды.
+/// Ошибка возвращается уже после того, как о ней сообщено
+pub async fn rules_signup(
+    env: &SignupEnv<'_>,
+    channel: ChannelId,
+    user: &User,
+    gid: &GuildId,
+    rules: UResult<Vec<String>>,
+) -> Result<(), BotError> {
+    let response = MessageBuilder::new()
+        .push("Отправляю тебе свод правил, ")
+        .push_bold_safe(&user.name)
+        .push("!")
+        .build();
+    env.ops.send_message(channel, &response).await?;
+
+    let result = match rules {
+        Ok(rules) => signup_session(env, user, gid, rules).await,
+        Err(why) => Err(why),
+    };
+    let why = match result {
+        Ok(()) => return Ok(()),
+        Err(why) => BotError::from(why).with_context("Signing up the user via 'rules' command"),
+    };
+    let response = why.user_message();
+    let direct = match env.ops.open_direct(user).await {
+        Ok(direct) => env.ops.send_message(direct, &response).await,
+        Err(why) => Err(why),
+    };
+    if direct.is_err() {
+        env.ops.send_message(channel, &response).await?;
     }
+    Err(why)
+}
 
-    let msg = MessageBuilder::new()
-        .push_bold_line_safe("Принимаете ли вы свод правил гильдии? (Да/Нет)")
-        .build();
-    loop {
-        if !user_is_in_guild(ctx, user, gid).await? {
-            send_privately(ctx, user, "Увы, вы больше не состоите в группе гильдии!").await?;
-            return Ok(());
+/// Сессия регистрации пользователя
+///
+/// Регистрация проходит в личных сообщениях, а если они
+/// закрыты - в приватной ветке канала знакомства
+/// (см. [`conversation::ConversationChannel`]). Сессия
+/// регистрируется в [`sessions::SessionRegistry`] и может
+/// быть прервана извне
+pub async fn signup_session(env: &SignupEnv<'_>, user: &User, gid: &GuildId, rules: Vec<String>) -> UResult {
+    let ops = env.ops;
+    match ops.check_membership(user.id, *gid).await {
+        Membership::Present => (),
+        Membership::ApiFailure(why) => return Err(why.into()),
+        Membership::NotMember | Membership::LeftDuringSession => {
+            return Err(BotError::UserInput(UserInputError::NotInGuild).into())
+        }
+    }
+    let config = env.config;
+    let session = env.sessions.register(*gid, user, config.signup.session_takeover)?;
+
+    let mut paragraphs = rules
+        .into_iter()
+        .map(|paragraph| MessageBuilder::new().push(paragraph).build());
+    let first = paragraphs
+        .next()
+        .ok_or(BotError::Config("The rules file is empty".to_owned()))?;
+    let timeout = Duration::from_secs(config.signup.reply_timeout_secs);
+    let conversation = Conversation::open(
+        ops,
+        user,
+        gid,
+        config.signup.onboarding_channel_id,
+        &first,
+        timeout,
+    )
+    .await?;
+
+    let dialog = tokio::select! {
+        outcome = run_signup(ops, &conversation, user, gid, &config.signup, paragraphs) => outcome,
+        _ = session.cancelled() => {
+            // Пользователь мог покинуть группу, поэтому сообщение не обязательно дойдёт
+            let _ = conversation.say(ops, "Эта сессия регистрации завершена.").await;
+            Ok(SignupOutcome::Cancelled)
         }
-        match query_from_user(ctx, user, &msg)
-            .await
-            .map(|m| m.to_lowercase())
-        {
-            Ok(r) => match r.as_str() {
-                "да" | "+" | "ок" | "yes" | "y" => break,
-                "нет" | "no" | "-" | "n" => {
-                    send_privately(ctx, user, "Ну, на нет и суда нет! Если вдруг передумаешь - введи команду `!rules` в чате гильдии!").await?;
-                    return Ok(());
-                }
-                _ => {
-                    send_privately(ctx, user, "Вы можете ответить только 'Да' или 'Нет'").await?;
-                    continue;
-                }
-            },
-            Err(_) => continue,
+    };
+    let result = match dialog {
+        Ok(SignupOutcome::Completed(application)) => finish_signup(env, &conversation, application).await,
+        Ok(_) => Ok(()),
+        Err(why) => {
+            let why = BotError::from(why);
+            if why.ends_conversation() {
+                conversation.say(ops, &why.user_message()).await
+            } else {
+                Err(why.into())
+            }
         }
+    };
+    let closed = conversation.close(ops).await;
+    result.and(closed)
+}
+
+/// Передача заявки на рассмотрение или её немедленное одобрение
+async fn finish_signup(env: &SignupEnv<'_>, conversation: &Conversation, application: approval::Application) -> UResult {
+    let approval_config = &env.config.signup.approval;
+    if approval_config.enabled {
+        let staff_channel = ChannelId(approval_config.staff_channel_id);
+        approval::submit_application(env.ops, staff_channel, env.approvals, application).await?;
+
+        let msg = MessageBuilder::new()
+            .push_bold_line_safe(
+                "Спасибо! Твоя заявка передана администрации гильдии, я сообщу тебе о решении.",
+            )
+            .build();
+        return conversation.say(env.ops, &msg).await;
     }
 
+    approval::complete_signup(env.ops, &env.config.signup, env.storage, &application).await?;
+
     let msg = MessageBuilder::new()
         .push_bold_line_safe(
-            "Теперь сообщи мне пожалуйста свой ник в игре, и я поставлю тебе его в группе",
+            "Всё готово! Тебе выдана роль в группе и поставлен псевдоним. Приятной игры!",
         )
         .build();
-    let nickname = loop {
-        if !user_is_in_guild(ctx, user, gid).await? {
-            send_privately(ctx, user, "Увы, вы больше не состоите в группе гильдии!").await?;
-            return Ok(());
-        }
-        match query_from_user(ctx, user, &msg).await {
-            Ok(r) => break r,
-            Err(_) => continue,
+    conversation.say(env.ops, &msg).await
+}
+
+/// Проверка, что пользователь всё ещё состоит в группе
+///
+/// Если пользователь покинул группу, ему сообщается об этом
+async fn still_member(ops: &dyn DiscordOps, conversation: &Conversation, gid: &GuildId) -> UResult<bool> {
+    match ops.check_membership(conversation.user_id(), *gid).await.in_session() {
+        Membership::Present => Ok(true),
+        Membership::ApiFailure(why) => Err(why.into()),
+        Membership::NotMember | Membership::LeftDuringSession => {
+            conversation.say(ops, "Увы, вы больше не состоите в группе гильдии!").await?;
+            Ok(false)
         }
-    };
+    }
+}
 
-    let role_id: u64 = 1037417494178181231; // TODO: Читать из конфига
-    ctx.http
-        .add_member_role(
-            gid.0,
-            user.id.0,
-            role_id,
-            Some("Автоматическое назначение роли"),
-        )
-        .await?;
-    ctx.http
-        .get_guild(gid.0)
-        .await?
-        .edit_member(&ctx.http, user.id.0, |member| member.nickname(nickname))
-        .await?;
+/// Диалог регистрации в открытом канале общения
+///
+/// Показывает правила, запрашивает их принятие, игровой ник
+/// и ответы на анкету. Отказавшемуся от правил пользователю
+/// сразу выдаются гостевые роли, если они настроены
+pub async fn run_signup(
+    ops: &dyn DiscordOps,
+    conversation: &Conversation,
+    user: &User,
+    gid: &GuildId,
+    config: &SignupConfig,
+    rules: impl Iterator<Item = String>,
+) -> UResult<SignupOutcome> {
+    for paragraph in rules {
+        conversation.say(ops, &paragraph).await?;
+    }
+
+    if !still_member(ops, conversation, gid).await? {
+        return Ok(SignupOutcome::Left);
+    }
+    if !conversation.ask_confirm(ops, "Принимаете ли вы свод правил гильдии? (Да/Нет)").await? {
+        let plan = roles::plan_roles(config, &[roles::rules_answer(false)]);
+        if plan.is_empty() {
+            conversation.say(ops, "Ну, на нет и суда нет! Если вдруг передумаешь - введи команду `!rules` в чате гильдии!").await?;
+        } else {
+            roles::apply_roles(ops, gid, user.id, &plan).await?;
+            conversation.say(ops, "Хорошо! Тебе выдан гостевой доступ к группе. Если вдруг передумаешь - введи команду `!rules` в чате гильдии!").await?;
+        }
+        return Ok(SignupOutcome::Declined);
+    }
 
+    if !still_member(ops, conversation, gid).await? {
+        return Ok(SignupOutcome::Left);
+    }
     let msg = MessageBuilder::new()
         .push_bold_line_safe(
-            "Всё готово! Тебе выдана роль в группе и поставлен псевдоним. Приятной игры!",
+            "Теперь сообщи мне пожалуйста свой ник в игре, и я поставлю тебе его в группе",
         )
         .build();
-    send_privately(ctx, user, &msg).await?;
+    let nickname = loop {
+        let reply = conversation.ask_text(ops, &msg).await?;
+        match validate_nickname(&reply) {
+            Ok(nickname) => break nickname,
+            Err(hint) => conversation.say(ops, &format!("{}, попробуй ещё раз", hint)).await?,
+        }
+    };
 
-    Ok(())
+    let mut answers = vec![roles::rules_answer(true)];
+    match questionnaire::run_questionnaire(ops, conversation, gid, &config.questions).await? {
+        Some(questionnaire) => answers.extend(questionnaire),
+        None => {
+            conversation.say(ops, "Увы, вы больше не состоите в группе гильдии!").await?;
+            return Ok(SignupOutcome::Left);
+        }
+    };
+    Ok(SignupOutcome::Completed(approval::Application::new(*gid, user, *conversation.channel(), nickname, answers)))
 }
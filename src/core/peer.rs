@@ -0,0 +1,123 @@
+use crate::prelude::*;
+use slog::{o, Logger};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use super::application::build_command_server;
+use super::auth::BridgeAuth;
+use super::metrics::Metrics;
+use super::requests::{BridgeRequest, BridgeResponse, RequestHandler, SignedRequest};
+use super::transport::{BridgeSender, TransportKind};
+
+/// Обработчик, сохраняющий все полученные команды
+struct RecordingHandler {
+    received: Mutex<mpsc::Sender<Command>>,
+}
+
+impl CommandHandler for RecordingHandler {
+    fn forward_message(&self, msg: Command) -> UResult {
+        let received = match self.received.lock() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("Mutex on received commands seems to be poisoned".into()).into()),
+        };
+        received
+            .send(msg)
+            .map_err(|_| BotError::Internal("Test peer receiver doesn't seem to exist anymore".into()).into())
+    }
+}
+
+impl RequestHandler for RecordingHandler {
+    fn handle_request(&self, request: SignedRequest) -> BridgeResponse {
+        match request.request {
+            BridgeRequest::Status => BridgeResponse::Status {
+                bot_version: "loopback".to_owned(),
+                protocol_versions: super::protocol::supported_versions(),
+                paused: false,
+                registered_members: 0,
+            },
+            other => BridgeResponse::Unsupported {
+                request: format!("{:?}", other),
+            },
+        }
+    }
+}
+
+/// Тестовый собеседник моста
+///
+/// Поднимает сервер команд с тем же диспетчером, что и у
+/// бота, и сохраняет все полученные команды
+pub struct LoopbackPeer {
+    pub config: BridgeConfig,
+    received: mpsc::Receiver<Command>,
+}
+
+impl LoopbackPeer {
+    /// Собеседник на временном Unix-сокете
+    pub fn spawn() -> UResult<Self> {
+        let socket = std::env::temp_dir().join(format!("qcorsar.peer.{}.sock", unique_nano()));
+        let mut config = BridgeConfig::default();
+        config.socket.listen_path = socket.to_string_lossy().into_owned();
+        config.socket.peer_path = config.socket.listen_path.clone();
+        config.socket.check_peer_uid = false;
+        Self::spawn_with(config)
+    }
+
+    /// Собеседник на свободном порту localhost
+    pub fn spawn_tcp(tls: TlsConfig) -> UResult<Self> {
+        let mut config = BridgeConfig::default();
+        config.transport.kind = TransportKind::Tcp;
+        config.transport.listen_addr = "127.0.0.1:0".to_owned();
+        config.transport.tls = tls;
+        Self::spawn_with(config)
+    }
+
+    fn spawn_with(mut config: BridgeConfig) -> UResult<Self> {
+        let (tx, received) = mpsc::channel();
+        let (ready_tx, ready_rx) = mpsc::channel();
+        let handler = Arc::new(RecordingHandler {
+            received: Mutex::new(tx),
+        });
+
+        let server_config = config.clone();
+        thread::spawn(move || {
+            let logger = Logger::root(slog::Discard, o!());
+            match build_command_server(&server_config, handler, Arc::new(Metrics::default()), &logger) {
+                Ok(server) => {
+                    let _ = ready_tx.send(Ok(server.local_addr()));
+                    let _ = server.listen();
+                }
+                Err(why) => {
+                    let _ = ready_tx.send(Err(why.to_string()));
+                }
+            }
+        });
+        let local_addr = ready_rx
+            .recv_timeout(Duration::from_secs(5))
+            .map_err(|_| BotError::TimedOut)?
+            .map_err(BotError::Protocol)?;
+
+        if let Some(addr) = local_addr {
+            config.transport.peer_addr = addr.to_string();
+        }
+        Ok(Self { config, received })
+    }
+
+    /// Отправитель команд, подключённый к этому собеседнику
+    pub fn sender(&self) -> BridgeSender {
+        let auth = BridgeAuth::new(&self.config.auth).expect("loopback peer signing");
+        BridgeSender::from_config(&self.config, Arc::new(auth)).expect("loopback peer sender")
+    }
+
+    pub fn recv(&self) -> Option<Command> {
+        self.received.recv_timeout(Duration::from_secs(5)).ok()
+    }
+}
+
+impl Drop for LoopbackPeer {
+    fn drop(&mut self) {
+        if self.config.transport.kind == TransportKind::Unix {
+            let _ = std::fs::remove_file(&self.config.socket.listen_path);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// Версия протокола в том виде, в котором она передаётся
+///
+/// `None`, если версия не является целым числом
+fn wire_version<V: Serialize>(version: &V) -> Option<u64> {
+    serde_json::to_value(version).ok().and_then(|value| value.as_u64())
+}
+
+/// Текущая версия протокола qcproto
+pub fn current_version() -> Option<u64> {
+    wire_version(&PROTOCOL_VERSION)
+}
+
+/// Поддерживаемые версии протокола, от новой к старой
+///
+/// Кроме текущей поддерживается предыдущая версия, чтобы
+/// боты Discord и Telegram можно было обновлять по очереди.
+/// По TCP версия выбирается при подключении (см. [`Hello`]),
+/// по сокетам Unix приветствия нет, и принимаются команды
+/// любой поддерживаемой версии
+pub fn supported_versions() -> Vec<u64> {
+    current_version()
+        .into_iter()
+        .flat_map(|current| std::iter::once(current).chain(current.checked_sub(1)))
+        .collect()
+}
+
+/// Признак поддерживаемой версии, нечисловые версии не поддерживаются
+pub fn is_supported(version: Option<u64>) -> bool {
+    version.is_some_and(|version| supported_versions().contains(&version))
+}
+
+/// Версия протокола команды
+pub fn command_version(cmd: &Command) -> Option<u64> {
+    wire_version(&cmd.protocol_version)
+}
+
+/// Выбор наибольшей версии, поддерживаемой обеими сторонами
+pub fn negotiate(offered: &[u64]) -> Option<u64> {
+    supported_versions()
+        .into_iter()
+        .find(|version| offered.contains(version))
+}
+
+/// Указание версии протокола в команде
+pub fn stamp(cmd: &mut Command, version: u64) -> UResult {
+    cmd.protocol_version = serde_json::from_value(version.into())?;
+    Ok(())
+}
+
+/// Приветствие при подключении к серверу команд
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hello {
+    pub versions: Vec<u64>,
+}
+
+impl Hello {
+    pub fn new() -> Self {
+        Self {
+            versions: supported_versions(),
+        }
+    }
+}
+
+impl Default for Hello {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Ответ сервера команд на приветствие
+///
+/// `version` - выбранная версия протокола, `None`, если
+/// общей версии нет
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelloReply {
+    pub version: Option<u64>,
+    pub supported: Vec<u64>,
+}
+
+impl HelloReply {
+    pub fn to(hello: &Hello) -> Self {
+        Self {
+            version: negotiate(&hello.versions),
+            supported: supported_versions(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::bridge::forward_command;
+
+    #[test]
+    fn previous_protocol_version_is_supported() {
+        let current = current_version().unwrap();
+        let expected: Vec<u64> = std::iter::once(current).chain(current.checked_sub(1)).collect();
+        assert_eq!(supported_versions(), expected);
+        assert_eq!(negotiate(&[current + 1, current]), Some(current));
+        if let Some(previous) = current.checked_sub(1) {
+            assert!(is_supported(Some(previous)));
+            assert_eq!(negotiate(&[previous]), Some(previous));
+        }
+        assert!(!is_supported(Some(current + 1)));
+        assert!(!is_supported(None));
+
+        let mut cmd = forward_command("telegram_server_id".to_owned(), "tg".to_owned(), "Привет".to_owned());
+        stamp(&mut cmd, current).unwrap();
+        assert_eq!(command_version(&cmd), Some(current));
+    }
+}
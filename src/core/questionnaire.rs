@@ -0,0 +1,253 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::{model::prelude::*, utils::MessageBuilder};
+use std::collections::HashMap;
+use std::time::Duration;
+
+use super::conversation::{match_choice, Conversation};
+use super::discord::DiscordOps;
+
+/// Идентификатор перехода, завершающий анкету
+pub const END_OF_QUESTIONNAIRE: &str = "end";
+
+/// Вопрос анкеты регистрации
+///
+/// Вопросы задаются в конфигурации (`signup.questions`) и
+/// задаются пользователю по порядку. Поля `branches` и `next`
+/// позволяют изменить порядок: `branches` сопоставляет ответ
+/// с идентификатором следующего вопроса, `next` задаёт переход
+/// по умолчанию. Переход на `end` завершает анкету. Поле
+/// `timeout_secs` переопределяет время ожидания ответа.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Question {
+    pub id: String,
+    pub prompt: String,
+    #[serde(flatten)]
+    pub kind: AnswerKind,
+    #[serde(default)]
+    pub branches: HashMap<String, String>,
+    #[serde(default)]
+    pub next: Option<String>,
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+/// Тип ожидаемого ответа и правила его проверки
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AnswerKind {
+    Text {
+        #[serde(default)]
+        min_length: Option<usize>,
+        #[serde(default)]
+        max_length: Option<usize>,
+    },
+    Number {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    Choice {
+        options: Vec<String>,
+    },
+}
+
+/// Ответ пользователя на вопрос анкеты
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Answer {
+    pub question_id: String,
+    pub prompt: String,
+    pub value: String,
+}
+
+impl Question {
+    /// Текст вопроса в том виде, в котором он отправляется пользователю
+    pub fn render(&self) -> String {
+        let mut msg = MessageBuilder::new();
+        msg.push_bold_line_safe(&self.prompt);
+        if let AnswerKind::Choice { options } = &self.kind {
+            for (i, option) in options.iter().enumerate() {
+                msg.push_line_safe(format!("{}. {}", i + 1, option));
+            }
+        }
+        msg.build()
+    }
+
+    /// Проверка ответа пользователя
+    ///
+    /// Возвращает нормализованное значение ответа или
+    /// сообщение для пользователя с описанием ошибки
+    pub fn validate(&self, raw: &str) -> Result<String, String> {
+        let raw = raw.trim();
+        match &self.kind {
+            AnswerKind::Text { min_length, max_length } => {
+                let len = raw.chars().count();
+                if len == 0 || min_length.is_some_and(|min| len < min) {
+                    Err(format!("Ответ слишком короткий (минимум {} симв.)", min_length.unwrap_or(1)))
+                } else if max_length.is_some_and(|max| len > max) {
+                    Err(format!("Ответ слишком длинный (максимум {} симв.)", max_length.unwrap_or_default()))
+                } else {
+                    Ok(raw.to_owned())
+                }
+            }
+            AnswerKind::Number { min, max } => {
+                let value: i64 = raw.parse().map_err(|_| "Ответом должно быть целое число".to_owned())?;
+                if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+                    Err(format!(
+                        "Число должно быть в пределах от {} до {}",
+                        min.map_or("-∞".to_owned(), |v| v.to_string()),
+                        max.map_or("∞".to_owned(), |v| v.to_string())
+                    ))
+                } else {
+                    Ok(value.to_string())
+                }
+            }
+            AnswerKind::Choice { options } => match_choice(options, raw)
+                .cloned()
+                .ok_or("Выберите один из предложенных вариантов".to_owned()),
+        }
+    }
+
+    /// Определение следующего вопроса после данного ответа
+    ///
+    /// `None` означает переход к следующему вопросу по порядку
+    pub fn next_for(&self, answer: &str) -> Option<&str> {
+        self.branches
+            .iter()
+            .find(|(key, _)| key.to_lowercase() == answer.to_lowercase())
+            .map(|(_, next)| next.as_str())
+            .or(self.next.as_deref())
+    }
+}
+
+/// Проверка переходов между вопросами анкеты
+///
+/// Идентификаторы вопросов должны быть уникальны, переходы -
+/// вести на существующий вопрос далее по порядку или на `end`,
+/// а вопросы с выбором - содержать варианты ответа. Переходы
+/// только вперёд гарантируют, что анкета конечна
+pub fn validate_questions(questions: &[Question]) -> Result<(), String> {
+    let mut positions = HashMap::new();
+    for (position, question) in questions.iter().enumerate() {
+        if question.id == END_OF_QUESTIONNAIRE {
+            return Err(format!("Question id '{}' is reserved", END_OF_QUESTIONNAIRE));
+        }
+        if positions.insert(question.id.as_str(), position).is_some() {
+            return Err(format!("Duplicate question id '{}'", question.id));
+        }
+    }
+
+    for (position, question) in questions.iter().enumerate() {
+        if let AnswerKind::Choice { options } = &question.kind {
+            if options.is_empty() {
+                return Err(format!("Question '{}' has no options", question.id));
+            }
+        }
+        let targets = question.branches.values().chain(question.next.iter());
+        for target in targets.filter(|target| target.as_str() != END_OF_QUESTIONNAIRE) {
+            match positions.get(target.as_str()) {
+                None => return Err(format!("Question '{}' leads to unknown question '{}'", question.id, target)),
+                Some(&next) if next <= position => {
+                    return Err(format!("Question '{}' leads back to question '{}'", question.id, target))
+                }
+                Some(_) => (),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Проведение анкетирования пользователя
+///
+/// Задаёт пользователю вопросы анкеты с учётом переходов
+/// между ними. Возвращает `None`, если пользователь покинул
+/// группу гильдии во время анкетирования.
+pub async fn run_questionnaire(
+    ops: &dyn DiscordOps,
+    conversation: &Conversation,
+    gid: &GuildId,
+    questions: &[Question],
+) -> UResult<Option<Vec<Answer>>> {
+    let mut answers = Vec::new();
+    let mut current = 0;
+
+    while let Some(question) = questions.get(current) {
+        match ops.check_membership(conversation.user_id(), *gid).await.in_session() {
+            Membership::Present => (),
+            Membership::ApiFailure(why) => return Err(why.into()),
+            Membership::NotMember | Membership::LeftDuringSession => return Ok(None),
+        }
+        let conversation = match question.timeout_secs {
+            Some(secs) => conversation.with_timeout(Duration::from_secs(secs)),
+            None => *conversation,
+        };
+        let value = match &question.kind {
+            AnswerKind::Choice { options } => conversation.ask_choice(ops, &question.prompt, options).await?,
+            _ => {
+                let prompt = question.render();
+                loop {
+                    let reply = conversation.ask_text(ops, &prompt).await?;
+                    match question.validate(&reply) {
+                        Ok(value) => break value,
+                        Err(hint) => conversation.say(ops, &hint).await?,
+                    }
+                }
+            }
+        };
+
+        current = match question.next_for(&value) {
+            Some(END_OF_QUESTIONNAIRE) => questions.len(),
+            Some(id) => questions
+                .iter()
+                .position(|q| q.id == id)
+                .unwrap_or(current + 1),
+            None => current + 1,
+        };
+        answers.push(Answer {
+            question_id: question.id.clone(),
+            prompt: question.prompt.clone(),
+            value,
+        });
+    }
+
+    Ok(Some(answers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn questions(json: &str) -> Vec<Question> {
+        serde_json::from_str(json).expect("valid questions")
+    }
+
+    #[test]
+    fn question_graphs_must_lead_forward_to_known_questions() {
+        let valid = questions(
+            r#"[
+                {"id": "class", "prompt": "Класс?", "type": "choice", "options": ["Воин", "Маг"],
+                 "branches": {"Воин": "end", "Маг": "school"}},
+                {"id": "school", "prompt": "Школа магии?", "type": "text", "next": "end"}
+            ]"#,
+        );
+        assert!(validate_questions(&valid).is_ok());
+
+        let invalid = [
+            r#"[{"id": "a", "prompt": "?", "type": "text", "next": "missing"}]"#,
+            r#"[{"id": "a", "prompt": "?", "type": "text", "next": "a"}]"#,
+            r#"[{"id": "a", "prompt": "?", "type": "text"},
+                {"id": "b", "prompt": "?", "type": "choice", "options": ["x"], "branches": {"x": "a"}}]"#,
+            r#"[{"id": "a", "prompt": "?", "type": "choice", "options": []}]"#,
+            r#"[{"id": "a", "prompt": "?", "type": "text"}, {"id": "a", "prompt": "?", "type": "text"}]"#,
+        ];
+        for json in invalid {
+            assert!(validate_questions(&questions(json)).is_err(), "{}", json);
+        }
+
+        let mut config = BotConfig::default();
+        let looping = r#"[{"id": "a", "prompt": "?", "type": "text", "next": "a"}]"#;
+        assert!(config.set_value("signup.questions", looping).is_err());
+        assert!(config.signup.questions.is_empty());
+    }
+}
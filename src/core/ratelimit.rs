@@ -0,0 +1,231 @@
+use crate::prelude::*;
+use serenity::{model::prelude::*, prelude::*};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Наибольшее число корзин в одном ограничителе
+///
+/// Ключи мостов строятся из имён удалённых авторов, поэтому
+/// собеседник может создавать новые ключи без ограничений
+const MAX_BUCKETS: usize = 4096;
+
+/// Корзина токенов
+///
+/// Каждое действие расходует один токен. Токены
+/// восстанавливаются равномерно со скоростью
+/// `refill_per_minute`, но не сверх `capacity`
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn full(config: &TokenBucketConfig) -> Self {
+        Self {
+            tokens: config.capacity as f64,
+            updated_at: Instant::now(),
+        }
+    }
+
+    /// Восстановление токенов, возвращает `true`, если есть хотя бы один
+    fn refill(&mut self, config: &TokenBucketConfig) -> bool {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.updated_at).as_secs_f64();
+        let refill = elapsed * config.refill_per_minute as f64 / 60.0;
+        self.tokens = (self.tokens + refill).min(config.capacity as f64);
+        self.updated_at = now;
+        self.tokens >= 1.0
+    }
+
+    /// Признак того, что к моменту `now` корзина снова полна
+    ///
+    /// Полная корзина ничем не отличается от новой, поэтому
+    /// её можно удалить
+    fn is_full_at(&self, config: &TokenBucketConfig, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+        self.tokens + elapsed * config.refill_per_minute as f64 / 60.0 >= config.capacity as f64
+    }
+
+    fn try_take(&mut self, config: &TokenBucketConfig) -> bool {
+        if self.refill(config) {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Ограничитель частоты действий с отдельной корзиной на каждый ключ
+///
+/// Число корзин ограничено `MAX_BUCKETS`. Когда для нового
+/// ключа нет места, удаляются снова заполнившиеся корзины,
+/// а если таких нет - корзина, не использовавшаяся дольше
+/// всех
+#[derive(Debug)]
+pub struct KeyedLimiter<K> {
+    config: TokenBucketConfig,
+    buckets: Mutex<HashMap<K, TokenBucket>>,
+}
+
+impl<K: Eq + Hash> KeyedLimiter<K> {
+    pub fn new(config: TokenBucketConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Попытка совершить действие от имени ключа
+    ///
+    /// Возвращает `false`, если лимит исчерпан. Нулевая
+    /// ёмкость отключает ограничение
+    pub fn try_acquire(&self, key: K) -> bool {
+        self.try_acquire_if(key, || true)
+    }
+
+    /// Попытка совершить действие, которое также должно
+    /// разрешить условие `also`
+    ///
+    /// `also` вызывается, только если у ключа есть токен, а
+    /// токен расходуется, только если `also` вернул `true`
+    pub fn try_acquire_if<F: FnOnce() -> bool>(&self, key: K, also: F) -> bool {
+        if self.config.capacity == 0 {
+            return also();
+        }
+        let mut buckets = match self.buckets.lock() {
+            Ok(value) => value,
+            Err(_) => return also(),
+        };
+        let config = &self.config;
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&key) {
+            evict(&mut buckets, config);
+        }
+        let bucket = buckets.entry(key).or_insert_with(|| TokenBucket::full(config));
+        if !bucket.refill(config) || !also() {
+            return false;
+        }
+        bucket.try_take(config)
+    }
+}
+
+/// Освобождение места для новой корзины
+fn evict<K: Eq + Hash>(buckets: &mut HashMap<K, TokenBucket>, config: &TokenBucketConfig) {
+    let now = Instant::now();
+    buckets.retain(|_, bucket| !bucket.is_full_at(config, now));
+    if buckets.len() < MAX_BUCKETS {
+        return;
+    }
+    let stalest = buckets.values().map(|bucket| bucket.updated_at).min();
+    if let Some(stalest) = stalest {
+        buckets.retain(|_, bucket| bucket.updated_at != stalest);
+    }
+}
+
+/// Ограничитель частоты пересылки сообщений через мост
+///
+/// Сообщение пересылается, только если лимит не исчерпан
+/// ни у автора, ни у канала
+#[derive(Debug)]
+pub struct BridgeLimiter {
+    per_user: KeyedLimiter<String>,
+    per_channel: KeyedLimiter<u64>,
+}
+
+impl BridgeLimiter {
+    pub fn new(config: &BridgeLimitsConfig) -> Self {
+        Self {
+            per_user: KeyedLimiter::new(config.user.clone()),
+            per_channel: KeyedLimiter::new(config.channel.clone()),
+        }
+    }
+
+    /// Проверка лимитов для сообщения автора в канале
+    ///
+    /// Авторы из Telegram идентифицируются по имени, поэтому
+    /// ключом автора служит строка. Токен канала расходуется,
+    /// только если лимит автора не исчерпан, чтобы один автор
+    /// не мог занять весь лимит канала
+    pub fn try_acquire(&self, author: &str, channel_id: ChannelId) -> bool {
+        self.per_user
+            .try_acquire_if(author.to_owned(), || self.per_channel.try_acquire(channel_id.0))
+    }
+}
+
+pub struct BridgeLimiterKey;
+impl TypeMapKey for BridgeLimiterKey {
+    type Value = Arc<BridgeLimiter>;
+}
+
+/// Получение ограничителя частоты пересылки через мост
+pub async fn bridge_limiter(ctx: &Context) -> UResult<Arc<BridgeLimiter>> {
+    let data = ctx.data.read().await;
+    data.get::<BridgeLimiterKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Bridge rate limiter").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn limiter(capacity: u32, refill_per_minute: u32) -> KeyedLimiter<String> {
+        KeyedLimiter::new(TokenBucketConfig {
+            capacity,
+            refill_per_minute,
+        })
+    }
+
+    fn len(limiter: &KeyedLimiter<String>) -> usize {
+        limiter.buckets.lock().unwrap().len()
+    }
+
+    #[test]
+    fn refilled_buckets_are_evicted_for_new_keys() {
+        let limiter = limiter(1, 600_000);
+        for i in 0..MAX_BUCKETS {
+            assert!(limiter.try_acquire(format!("tg:{}", i)));
+        }
+        assert_eq!(len(&limiter), MAX_BUCKETS);
+        std::thread::sleep(Duration::from_millis(20));
+        assert!(limiter.try_acquire("tg:new".to_owned()));
+        assert_eq!(len(&limiter), 1);
+    }
+
+    #[test]
+    fn bucket_count_is_capped_when_nothing_refills() {
+        let limiter = limiter(1, 0);
+        for i in 0..MAX_BUCKETS + 10 {
+            assert!(limiter.try_acquire(format!("tg:{}", i)));
+        }
+        assert!(len(&limiter) <= MAX_BUCKETS);
+        assert!(!limiter.try_acquire(format!("tg:{}", MAX_BUCKETS + 9)));
+    }
+
+    #[test]
+    fn flooding_authors_do_not_drain_the_channel_limit() {
+        let limiter = BridgeLimiter::new(&BridgeLimitsConfig {
+            user: TokenBucketConfig {
+                capacity: 2,
+                refill_per_minute: 0,
+            },
+            channel: TokenBucketConfig {
+                capacity: 5,
+                refill_per_minute: 0,
+            },
+        });
+        let channel = ChannelId(10);
+
+        let flood = (0..10).filter(|_| limiter.try_acquire("ds:1", channel)).count();
+        assert_eq!(flood, 2);
+
+        let calm = (0..3).filter(|_| limiter.try_acquire("ds:2", channel)).count();
+        assert_eq!(calm, 2);
+        assert!(limiter.try_acquire("ds:3", channel));
+        assert!(!limiter.try_acquire("ds:4", channel));
+    }
+}
@@ -0,0 +1,163 @@
+use crate::prelude::*;
+use serenity::{model::prelude::*, prelude::*, utils::MessageBuilder};
+use slog::Logger;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Ограничение частоты уведомлений администрации об ошибках
+///
+/// Ошибки с одинаковыми событием, категорией и контекстом
+/// считаются одной и той же ошибкой. Повторы в пределах
+/// интервала не отправляются, а подсчитываются
+#[derive(Debug, Default)]
+pub struct ErrorThrottle {
+    reported: Mutex<HashMap<String, (Instant, u64)>>,
+}
+
+impl ErrorThrottle {
+    /// Решение об отправке уведомления
+    ///
+    /// Возвращает число пропущенных с прошлого уведомления
+    /// повторов или `None`, если уведомлять ещё рано
+    pub fn admit(&self, key: &str, interval: Duration, now: Instant) -> Option<u64> {
+        let mut reported = match self.reported.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        match reported.get_mut(key) {
+            Some((last, suppressed)) if now.duration_since(*last) < interval => {
+                *suppressed += 1;
+                None
+            }
+            Some(entry) => {
+                let suppressed = entry.1;
+                *entry = (now, 0);
+                Some(suppressed)
+            }
+            None => {
+                reported.insert(key.to_owned(), (now, 0));
+                Some(0)
+            }
+        }
+    }
+}
+
+/// Ключ, по которому сравниваются ошибки
+pub fn throttle_key(event: &str, error: &BotError) -> String {
+    format!("{}:{}:{}", event, error.kind(), error.context().unwrap_or_default())
+}
+
+pub struct ErrorThrottleKey;
+impl TypeMapKey for ErrorThrottleKey {
+    type Value = Arc<ErrorThrottle>;
+}
+
+/// Получение ограничителя уведомлений об ошибках
+pub async fn error_throttle(ctx: &Context) -> UResult<Arc<ErrorThrottle>> {
+    let data = ctx.data.read().await;
+    data.get::<ErrorThrottleKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Error throttle").into())
+}
+
+/// Централизованная обработка ошибок обработчиков событий
+///
+/// Записывает ошибку в лог, увеличивает счётчик ошибок
+/// соответствующей категории и, если в конфигурации указан
+/// канал администрации (`reporting.staff_channel_id`),
+/// уведомляет администрацию. Повторы одной и той же ошибки
+/// отправляются не чаще раза в `reporting.throttle_secs`
+/// секунд, число пропущенных повторов указывается
+/// в следующем уведомлении
+pub async fn report_error(ctx: &Context, logger: &Logger, event: &'static str, error: BotError) {
+    error!(logger, "Event handler failed"; "event" => event, &error);
+
+    if let Ok(metrics) = metrics::metrics(ctx).await {
+        metrics.increment("errors.total");
+        metrics.increment(&format!("errors.{}", error.kind()));
+    }
+
+    let config = match bot_config(ctx).await {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+    if config.reporting.staff_channel_id == 0 {
+        return;
+    }
+    let interval = Duration::from_secs(config.reporting.throttle_secs);
+    let suppressed = match error_throttle(ctx).await {
+        Ok(throttle) => match throttle.admit(&throttle_key(event, &error), interval, Instant::now()) {
+            Some(suppressed) => suppressed,
+            None => {
+                debug!(logger, "Staff notification suppressed"; "event" => event);
+                return;
+            }
+        },
+        Err(_) => 0,
+    };
+    let mut msg = MessageBuilder::new();
+    msg.push("Ошибка при обработке события ")
+        .push_mono_safe(event)
+        .push_line(":")
+        .push_codeblock_safe(error.to_string(), None);
+    if suppressed > 0 {
+        msg.push_italic_line(format!("Похожих ошибок с прошлого уведомления: {}", suppressed));
+    }
+    let msg = msg.build();
+    if let Err(why) = ChannelId(config.reporting.staff_channel_id)
+        .say(&ctx.http, msg)
+        .await
+    {
+        let why = BotError::from(why);
+        warn!(logger, "Could not notify the staff about an error"; &why);
+    }
+}
+
+/// Обращение к пользователю в приветственном канале
+///
+/// Используется, когда пользователю невозможно написать
+/// в личные сообщения. Канал задаётся в конфигурации
+/// (`signup.welcome_channel_id`)
+pub async fn welcome_fallback(ctx: &Context, user: &User) -> UResult {
+    let config = bot_config(ctx).await?;
+    if config.signup.welcome_channel_id == 0 {
+        return Err(BotError::Config("Welcome channel is not configured".to_owned()).into());
+    }
+    let msg = MessageBuilder::new()
+        .push("Добро пожаловать, ")
+        .mention(user)
+        .push("! Я не могу написать тебе в личные сообщения. ")
+        .push("Разреши их для участников сервера и введи команду `")
+        .push(&config.general.prefix)
+        .push("rules`, чтобы пройти регистрацию.")
+        .build();
+    ChannelId(config.signup.welcome_channel_id)
+        .say(&ctx.http, msg)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_errors_are_reported_once_per_interval() {
+        let throttle = ErrorThrottle::default();
+        let interval = Duration::from_secs(600);
+        let start = std::time::Instant::now();
+        let down = throttle_key("voice", &BotError::Bridge("peer is down".into()).with_context("announce"));
+        let again = throttle_key("voice", &BotError::Bridge("still down".into()).with_context("announce"));
+        let other = throttle_key("message", &BotError::Bridge("peer is down".into()));
+        assert_eq!(down, again);
+        assert_ne!(down, other);
+
+        assert_eq!(throttle.admit(&down, interval, start), Some(0));
+        assert_eq!(throttle.admit(&again, interval, start + Duration::from_secs(1)), None);
+        assert_eq!(throttle.admit(&again, interval, start + Duration::from_secs(2)), None);
+        assert_eq!(throttle.admit(&other, interval, start + Duration::from_secs(2)), Some(0));
+        assert_eq!(throttle.admit(&down, interval, start + interval), Some(2));
+        assert_eq!(throttle.admit(&down, interval, start + interval), None);
+    }
+}
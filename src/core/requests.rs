@@ -0,0 +1,197 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use serenity::{model::prelude::*, prelude::*};
+use slog::{o, Logger};
+
+use super::auth::Verification;
+use super::directory::{self, MemberInfo};
+
+/// Запрос к боту через мост
+///
+/// В отличие от команд qcproto, на запрос приходит ответ
+/// в том же подключении. Запросы к справочнику участников
+/// принимаются только подписанными, даже в режиме `migration`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum BridgeRequest {
+    /// Состояние бота и моста
+    Status,
+    /// Поиск участников по игровому нику или имени в Discord
+    MemberLookup { query: String },
+    /// Участники в сети
+    Online,
+    /// Статус присутствия участника
+    Presence { user_id: u64 },
+}
+
+impl BridgeRequest {
+    /// Раскрывает ли запрос ники и статусы участников
+    pub fn exposes_members(&self) -> bool {
+        !matches!(self, Self::Status)
+    }
+}
+
+/// Запрос с подписью (см. [`super::auth::BridgeAuth::verify_payload`])
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SignedRequest {
+    #[serde(flatten)]
+    pub request: BridgeRequest,
+    #[serde(default)]
+    pub signature: String,
+}
+
+/// Ответ бота на запрос
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "response", rename_all = "snake_case")]
+pub enum BridgeResponse {
+    Status {
+        bot_version: String,
+        protocol_versions: Vec<u64>,
+        paused: bool,
+        registered_members: usize,
+    },
+    Members {
+        members: Vec<MemberInfo>,
+    },
+    Presence {
+        user_id: u64,
+        status: Option<String>,
+    },
+    /// Запрос неизвестного вида
+    Unsupported {
+        request: String,
+    },
+    /// Команда неизвестного вида
+    UnsupportedCommand {
+        command: String,
+    },
+    Error {
+        message: String,
+    },
+}
+
+/// Обработчик запросов к боту
+pub trait RequestHandler {
+    fn handle_request(&self, request: SignedRequest) -> BridgeResponse;
+}
+
+/// Разбор запроса
+///
+/// Для запроса неизвестного вида возвращается готовый
+/// ответ `Unsupported`
+pub fn parse_request(value: Value) -> Result<SignedRequest, BridgeResponse> {
+    let kind = match value.get("request") {
+        Some(Value::String(kind)) => kind.clone(),
+        Some(other) => other.to_string(),
+        None => String::new(),
+    };
+    serde_json::from_value(value).map_err(|_| BridgeResponse::Unsupported { request: kind })
+}
+
+/// Можно ли ответить на запрос с такой проверкой подписи
+///
+/// Неподписанные запросы, принятые в режиме `migration`,
+/// получают только состояние бота
+pub fn is_permitted(request: &BridgeRequest, verification: Verification) -> bool {
+    !request.exposes_members() || verification == Verification::Signed
+}
+
+/// Ответ на запрос к боту
+pub async fn respond(ctx: &Context, logger: &Logger, signed: SignedRequest) -> UResult<BridgeResponse> {
+    let body = serde_json::to_string(&signed.request)?;
+    let verification = super::auth::bridge_auth(ctx)
+        .await?
+        .verify_payload("request", &body, &signed.signature);
+    let audit = logger.new(o!("audit" => "bridge", "request" => body));
+    let metrics = super::metrics::metrics(ctx).await?;
+    if !super::auth::admit(&metrics, &audit, verification) {
+        return Ok(BridgeResponse::Error {
+            message: "request is not authorized".to_owned(),
+        });
+    }
+    if !is_permitted(&signed.request, verification) {
+        warn!(audit, "Rejected an unsigned member directory request");
+        metrics.increment("bridge.auth.unsigned_directory");
+        return Ok(BridgeResponse::Error {
+            message: "member directory requests must be signed".to_owned(),
+        });
+    }
+
+    let config = bot_config(ctx).await?;
+    match signed.request {
+        BridgeRequest::Status => {
+            let paused = super::bridge::bridge_state(ctx).await?.is_paused();
+            let storage = super::storage::member_storage(ctx).await?;
+            Ok(BridgeResponse::Status {
+                bot_version: env!("CARGO_PKG_VERSION").to_owned(),
+                protocol_versions: super::protocol::supported_versions(),
+                paused,
+                registered_members: storage.all()?.len(),
+            })
+        }
+        BridgeRequest::MemberLookup { query } => {
+            let members = directory::search(directory::directory(ctx).await?, &query);
+            Ok(BridgeResponse::Members { members })
+        }
+        BridgeRequest::Online => {
+            let members = directory::online(directory::directory(ctx).await?);
+            Ok(BridgeResponse::Members { members })
+        }
+        BridgeRequest::Presence { user_id } => {
+            let status = ctx.cache.guild(config.general.guild_id).and_then(|guild| {
+                guild
+                    .presences
+                    .get(&UserId(user_id))
+                    .map(|presence| presence.status.name().to_owned())
+            });
+            Ok(BridgeResponse::Presence { user_id, status })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::auth::{AuthFailure, AuthMode, BridgeAuth};
+
+    #[test]
+    fn bridge_requests_are_parsed_with_signatures() {
+        let value = serde_json::json!({"request": "member_lookup", "query": "Innri", "signature": "v1:1:n:00"});
+        let signed = parse_request(value).unwrap();
+
+        assert_eq!(
+            signed.request,
+            BridgeRequest::MemberLookup {
+                query: "Innri".to_owned()
+            }
+        );
+        assert_eq!(signed.signature, "v1:1:n:00");
+
+        let auth = BridgeAuth::new(&BridgeAuthConfig {
+            secret: "secret".to_owned(),
+            mode: AuthMode::Required,
+            max_age_secs: 300,
+        })
+        .unwrap();
+        let body = serde_json::to_string(&signed.request).unwrap();
+        let signature = auth.sign_payload("request", &body);
+        assert_eq!(auth.verify_payload("request", &body, &signature), Verification::Signed);
+        assert_eq!(
+            auth.verify_payload("request", &body, &signed.signature),
+            Verification::Rejected(AuthFailure::BadSignature)
+        );
+    }
+
+    #[test]
+    fn member_directory_requires_signed_requests() {
+        let lookup = BridgeRequest::MemberLookup {
+            query: "Innri".to_owned(),
+        };
+        for request in [lookup, BridgeRequest::Online, BridgeRequest::Presence { user_id: 1 }] {
+            assert!(is_permitted(&request, Verification::Signed));
+            assert!(!is_permitted(&request, Verification::UnsignedAccepted));
+        }
+        assert!(is_permitted(&BridgeRequest::Status, Verification::UnsignedAccepted));
+    }
+}
@@ -0,0 +1,151 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::model::prelude::*;
+use std::collections::HashMap;
+
+use super::discord::DiscordOps;
+use super::questionnaire::Answer;
+
+/// Идентификатор псевдо-вопроса о принятии правил гильдии
+pub const RULES_QUESTION_ID: &str = "rules";
+pub const RULES_ACCEPTED: &str = "accepted";
+pub const RULES_DECLINED: &str = "declined";
+
+/// Правило назначения ролей по ответам анкеты
+///
+/// Правило срабатывает, если ответ на каждый вопрос из `when`
+/// совпадает с одним из перечисленных значений (без учёта
+/// регистра). Правило без условий срабатывает всегда. Ответ
+/// о принятии правил гильдии доступен под идентификатором
+/// `rules` со значениями `accepted` или `declined`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RoleRule {
+    pub when: HashMap<String, Vec<String>>,
+    pub add: Vec<u64>,
+    pub remove: Vec<u64>,
+}
+
+impl RoleRule {
+    pub fn matches(&self, answers: &[Answer]) -> bool {
+        self.when.iter().all(|(question, expected)| {
+            answers
+                .iter()
+                .find(|a| &a.question_id == question)
+                .is_some_and(|a| {
+                    expected
+                        .iter()
+                        .any(|value| value.to_lowercase() == a.value.to_lowercase())
+                })
+        })
+    }
+}
+
+/// Итоговый набор изменений ролей пользователя
+#[derive(Debug, Clone, Default)]
+pub struct RolePlan {
+    pub add: Vec<RoleId>,
+    pub remove: Vec<RoleId>,
+}
+
+impl RolePlan {
+    pub fn is_empty(&self) -> bool {
+        self.add.is_empty() && self.remove.is_empty()
+    }
+
+    fn add(&mut self, role: RoleId) {
+        self.remove.retain(|r| *r != role);
+        if !self.add.contains(&role) {
+            self.add.push(role);
+        }
+    }
+
+    fn remove(&mut self, role: RoleId) {
+        self.add.retain(|r| *r != role);
+        if !self.remove.contains(&role) {
+            self.remove.push(role);
+        }
+    }
+}
+
+/// Ответ о принятии или отклонении правил гильдии
+pub fn rules_answer(accepted: bool) -> Answer {
+    Answer {
+        question_id: RULES_QUESTION_ID.to_owned(),
+        prompt: "Принимаете ли вы свод правил гильдии?".to_owned(),
+        value: if accepted { RULES_ACCEPTED } else { RULES_DECLINED }.to_owned(),
+    }
+}
+
+/// Расчёт изменений ролей по ответам пользователя
+///
+/// Правила применяются по порядку, и более поздние правила
+/// имеют приоритет над ранними. Если правила не заданы,
+/// пользователю, принявшему правила гильдии, выдаётся роль
+/// участника (`signup.member_role_id`).
+pub fn plan_roles(config: &SignupConfig, answers: &[Answer]) -> RolePlan {
+    let mut plan = RolePlan::default();
+
+    if config.role_rules.is_empty() {
+        let accepted = answers
+            .iter()
+            .any(|a| a.question_id == RULES_QUESTION_ID && a.value == RULES_ACCEPTED);
+        if accepted {
+            plan.add(RoleId(config.member_role_id));
+        }
+        return plan;
+    }
+
+    for rule in config.role_rules.iter().filter(|rule| rule.matches(answers)) {
+        rule.add.iter().for_each(|role| plan.add(RoleId(*role)));
+        rule.remove.iter().for_each(|role| plan.remove(RoleId(*role)));
+    }
+    plan
+}
+
+/// Применение изменений ролей к участнику группы
+pub async fn apply_roles(ops: &dyn DiscordOps, gid: &GuildId, uid: UserId, plan: &RolePlan) -> UResult {
+    for role in &plan.add {
+        ops.add_role(*gid, uid, *role, "Автоматическое назначение роли").await?;
+    }
+    for role in &plan.remove {
+        ops.remove_role(*gid, uid, *role, "Автоматическое снятие роли").await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_rules_match_questionnaire_answers() {
+        let config = SignupConfig {
+            role_rules: vec![
+                RoleRule {
+                    when: Default::default(),
+                    add: vec![1],
+                    remove: Vec::new(),
+                },
+                RoleRule {
+                    when: [("class".to_owned(), vec!["маг".to_owned()])].into_iter().collect(),
+                    add: vec![2],
+                    remove: vec![1],
+                },
+            ],
+            ..Default::default()
+        };
+        let mage = Answer {
+            question_id: "class".to_owned(),
+            prompt: "Класс?".to_owned(),
+            value: "Маг".to_owned(),
+        };
+
+        let plan = plan_roles(&config, &[rules_answer(true), mage]);
+        assert_eq!(plan.add, vec![RoleId(2)]);
+        assert_eq!(plan.remove, vec![RoleId(1)]);
+
+        let plan = plan_roles(&SignupConfig::default(), &[rules_answer(true)]);
+        assert_eq!(plan.add, vec![RoleId(SignupConfig::default().member_role_id)]);
+    }
+}
@@ -0,0 +1,203 @@
+use crate::prelude::*;
+use chrono::{DateTime, Local};
+use serenity::{model::prelude::*, prelude::*};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use tokio::sync::Notify;
+
+/// Сведения об активной сессии регистрации
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub user_name: String,
+    pub started_at: DateTime<Local>,
+    id: usize,
+    cancel: Arc<Notify>,
+}
+
+/// Реестр активных сессий регистрации
+///
+/// Сессии индексируются парой из идентификатора группы
+/// и идентификатора пользователя. У пользователя может быть
+/// только одна активная сессия в группе
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    sessions: RwLock<HashMap<(GuildId, UserId), SessionInfo>>,
+}
+
+impl SessionRegistry {
+    /// Регистрация новой сессии
+    ///
+    /// Если у пользователя уже есть активная сессия, она
+    /// прерывается при `take_over`, иначе регистрация
+    /// отклоняется. Сессия остаётся в реестре до тех пор,
+    /// пока существует возвращённый страж
+    pub fn register(self: &Arc<Self>, gid: GuildId, user: &User, take_over: bool) -> UResult<SessionGuard> {
+        let key = (gid, user.id);
+        let info = SessionInfo {
+            user_name: user.name.clone(),
+            started_at: Local::now(),
+            id: unique_id(),
+            cancel: Arc::new(Notify::new()),
+        };
+        let guard = SessionGuard {
+            registry: Arc::clone(self),
+            key,
+            id: info.id,
+            cancel: Arc::clone(&info.cancel),
+        };
+
+        let mut sessions = match self.sessions.write() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("RwLock on sessions seems to be poisoned".into()).into()),
+        };
+        if let Some(previous) = sessions.get(&key) {
+            if !take_over {
+                return Err(BotError::UserInput(UserInputError::SessionActive).into());
+            }
+            previous.cancel.notify_one();
+        }
+        sessions.insert(key, info);
+        Ok(guard)
+    }
+
+    /// Список активных сессий
+    pub fn list(&self) -> UResult<Vec<((GuildId, UserId), SessionInfo)>> {
+        let sessions = match self.sessions.read() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("RwLock on sessions seems to be poisoned".into()).into()),
+        };
+        Ok(sessions
+            .iter()
+            .map(|(key, info)| (*key, info.clone()))
+            .collect())
+    }
+
+    /// Прерывание активной сессии пользователя
+    ///
+    /// Возвращает `false`, если активной сессии не было
+    pub fn cancel(&self, gid: GuildId, uid: UserId) -> UResult<bool> {
+        let mut sessions = match self.sessions.write() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Internal("RwLock on sessions seems to be poisoned".into()).into()),
+        };
+        match sessions.remove(&(gid, uid)) {
+            Some(info) => {
+                info.cancel.notify_one();
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    fn remove(&self, key: &(GuildId, UserId), id: usize) {
+        if let Ok(mut sessions) = self.sessions.write() {
+            if sessions.get(key).is_some_and(|info| info.id == id) {
+                sessions.remove(key);
+            }
+        }
+    }
+}
+
+/// Страж активной сессии регистрации
+///
+/// При уничтожении удаляет сессию из реестра, если она
+/// не была замещена более новой сессией
+pub struct SessionGuard {
+    registry: Arc<SessionRegistry>,
+    key: (GuildId, UserId),
+    id: usize,
+    cancel: Arc<Notify>,
+}
+
+impl SessionGuard {
+    /// Ожидание прерывания сессии извне
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await
+    }
+}
+
+impl Drop for SessionGuard {
+    fn drop(&mut self) {
+        self.registry.remove(&self.key, self.id);
+    }
+}
+
+pub struct SessionsKey;
+impl TypeMapKey for SessionsKey {
+    type Value = Arc<SessionRegistry>;
+}
+
+/// Получение реестра активных сессий регистрации
+pub async fn session_registry(ctx: &Context) -> UResult<Arc<SessionRegistry>> {
+    let data = ctx.data.read().await;
+    data.get::<SessionsKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Signup sessions registry").into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const GUILD: GuildId = GuildId(100);
+
+    fn user(id: u64) -> User {
+        let mut user = User::default();
+        user.id = UserId(id);
+        user
+    }
+
+    /// Признак того, что сессию прервали извне
+    async fn is_cancelled(guard: &SessionGuard) -> bool {
+        tokio::time::timeout(Duration::from_millis(50), guard.cancelled())
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn second_session_is_rejected_without_takeover() {
+        let registry = Arc::new(SessionRegistry::default());
+        let first = registry.register(GUILD, &user(1), false).unwrap();
+
+        let why = registry.register(GUILD, &user(1), false).err().map(BotError::from);
+        assert!(matches!(why, Some(BotError::UserInput(UserInputError::SessionActive))));
+        assert!(registry.register(GuildId(101), &user(1), false).is_ok());
+        assert!(registry.register(GUILD, &user(2), false).is_ok());
+        assert!(!is_cancelled(&first).await);
+    }
+
+    #[tokio::test]
+    async fn takeover_cancels_the_previous_session_and_keeps_the_newer_one() {
+        let registry = Arc::new(SessionRegistry::default());
+        let first = registry.register(GUILD, &user(1), true).unwrap();
+        let second = registry.register(GUILD, &user(1), true).unwrap();
+        assert!(is_cancelled(&first).await);
+
+        drop(first);
+        let active = registry.list().unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].1.id, second.id);
+
+        drop(second);
+        assert!(registry.list().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn member_removal_cancels_the_session() {
+        let registry = Arc::new(SessionRegistry::default());
+        let guard = registry.register(GUILD, &user(1), false).unwrap();
+
+        assert!(registry.cancel(GUILD, UserId(1)).unwrap());
+        assert!(is_cancelled(&guard).await);
+        assert!(registry.list().unwrap().is_empty());
+        assert!(!registry.cancel(GUILD, UserId(1)).unwrap());
+
+        // Сессия, начатая заново после выхода, не удаляется стражем прерванной
+        let rejoined = registry.register(GUILD, &user(1), false).unwrap();
+        drop(guard);
+        assert_eq!(registry.list().unwrap().len(), 1);
+        drop(rejoined);
+        assert!(registry.list().unwrap().is_empty());
+    }
+}
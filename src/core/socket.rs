@@ -0,0 +1,289 @@
+use crate::prelude::*;
+use std::fs;
+use std::io::ErrorKind;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// Права доступа к сокетам моста
+const SOCKET_MODE: u32 = 0o600;
+/// Права доступа к директории сокетов моста
+const SOCKET_DIR_MODE: u32 = 0o700;
+/// Маска прав на время создания сокета, оставляет `SOCKET_MODE`
+const SOCKET_UMASK: libc::mode_t = 0o177;
+
+/// Директория сокетов этого экземпляра бота
+pub fn socket_dir(config: &SocketConfig) -> PathBuf {
+    let runtime_dir = match config.runtime_dir.trim() {
+        "" => std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(std::env::temp_dir),
+        dir => PathBuf::from(dir),
+    };
+    let instance = match config.instance.trim() {
+        "" => "default",
+        instance => instance,
+    };
+    runtime_dir.join("qcorsar").join(instance)
+}
+
+fn resolve(config: &SocketConfig, explicit: &str, file_name: &str) -> PathBuf {
+    match explicit.trim() {
+        "" => socket_dir(config).join(file_name),
+        path => PathBuf::from(path),
+    }
+}
+
+/// Путь сокета, на котором бот принимает команды
+pub fn listen_path(config: &SocketConfig) -> PathBuf {
+    resolve(config, &config.listen_path, "discord.sock")
+}
+
+/// Путь сокета бота Telegram
+pub fn peer_path(config: &SocketConfig) -> PathBuf {
+    resolve(config, &config.peer_path, "tg.sock")
+}
+
+/// Проверка, что директорию не может изменить другой пользователь
+///
+/// Директория должна принадлежать пользователю бота и не
+/// давать никаких прав группе и остальным. Символическая
+/// ссылка вместо директории не принимается
+pub fn check_private_dir(dir: &Path) -> UResult {
+    let metadata = fs::symlink_metadata(dir)?;
+    // SAFETY: getuid не имеет предусловий и не может завершиться ошибкой
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() {
+        return Err(BotError::Config(format!("{} is not a directory", dir.display())).into());
+    }
+    if metadata.uid() != uid {
+        return Err(BotError::Config(format!(
+            "{} belongs to uid {}, not to the bot user {}",
+            dir.display(),
+            metadata.uid(),
+            uid
+        ))
+        .into());
+    }
+    if metadata.mode() & 0o077 != 0 {
+        return Err(BotError::Config(format!(
+            "{} is accessible to other users (mode {:o}), expected {:o}",
+            dir.display(),
+            metadata.mode() & 0o777,
+            SOCKET_DIR_MODE
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Подготовка директории сокетов этого экземпляра
+///
+/// Создаёт директорию `qcorsar` и директорию экземпляра в
+/// ней. Если они уже существуют, они должны пройти
+/// [`check_private_dir`]: без `XDG_RUNTIME_DIR` директория
+/// находится по предсказуемому пути во временной директории,
+/// и другой пользователь мог создать её заранее, чтобы
+/// подменить сокеты
+pub fn prepare_socket_dir(config: &SocketConfig) -> UResult {
+    let dir = socket_dir(config);
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(SOCKET_DIR_MODE)
+        .create(&dir)?;
+    if let Some(parent) = dir.parent() {
+        check_private_dir(parent)?;
+    }
+    check_private_dir(&dir)
+}
+
+/// Подготовка пути сокета перед запуском сервера
+///
+/// Создаёт недоступную другим пользователям директорию, если
+/// её нет, и удаляет оставшийся от прошлого запуска сокет.
+/// Директорию явно указанного пути выбирает администратор,
+/// директория по умолчанию проверяется отдельно (см.
+/// [`prepare_socket_dir`]). Если
+/// сокет ещё принимает подключения или по этому пути
+/// находится не сокет, возвращается ошибка конфигурации
+pub fn prepare_socket(path: &Path) -> UResult {
+    if let Some(dir) = path.parent() {
+        if !dir.exists() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(SOCKET_DIR_MODE)
+                .create(dir)?;
+        }
+    }
+
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(why) if why.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(why) => return Err(why.into()),
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(BotError::Config(format!("{} exists and is not a socket", path.display())).into());
+    }
+    match UnixStream::connect(path) {
+        Ok(_) => Err(BotError::Config(format!("{} is used by another running instance", path.display())).into()),
+        Err(why) if why.kind() == ErrorKind::ConnectionRefused => {
+            fs::remove_file(path)?;
+            Ok(())
+        }
+        Err(why) => Err(why.into()),
+    }
+}
+
+/// Ограничение доступа к сокету владельцем
+pub fn restrict_permissions(path: &Path) -> UResult {
+    fs::set_permissions(path, fs::Permissions::from_mode(SOCKET_MODE))?;
+    Ok(())
+}
+
+/// Создание сокета, доступного только владельцу
+///
+/// На время создания устанавливается маска прав
+/// `SOCKET_UMASK`, чтобы сокет ни мгновения не был
+/// доступен другим пользователям, затем права
+/// закрепляются явно
+pub fn bind_restricted(path: &Path) -> UResult<UnixListener> {
+    // SAFETY: umask не имеет предусловий и не может завершиться ошибкой
+    let previous = unsafe { libc::umask(SOCKET_UMASK) };
+    let listener = UnixListener::bind(path);
+    // SAFETY: см. выше
+    unsafe { libc::umask(previous) };
+    let listener = listener
+        .map_err(|why| BotError::Config(format!("Could not listen on {}: {}", path.display(), why)))?;
+    restrict_permissions(path)?;
+    Ok(listener)
+}
+
+/// Идентификатор пользователя процесса на другой стороне сокета
+pub fn peer_uid(stream: &UnixStream) -> std::io::Result<u32> {
+    let mut cred = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` и `len` соответствуют буферу, ожидаемому SO_PEERCRED
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut cred as *mut libc::ucred as *mut libc::c_void,
+            &mut len,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    Ok(cred.uid)
+}
+
+/// Пользователи, которым разрешено подключаться к серверу
+///
+/// Пустой список означает пользователя, под которым
+/// запущен бот
+pub fn allowed_uids(config: &SocketConfig) -> Vec<u32> {
+    if config.allowed_uids.is_empty() {
+        // SAFETY: getuid не имеет предусловий и не может завершиться ошибкой
+        vec![unsafe { libc::getuid() }]
+    } else {
+        config.allowed_uids.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime_config(name: &str) -> SocketConfig {
+        let runtime_dir = std::env::temp_dir().join(format!("qcorsar.runtime.{}.{}", name, unique_nano()));
+        SocketConfig {
+            runtime_dir: runtime_dir.to_string_lossy().into_owned(),
+            instance: "test".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn socket_dirs_are_created_private() {
+        let config = runtime_config("fresh");
+        prepare_socket_dir(&config).unwrap();
+
+        let mode = fs::metadata(socket_dir(&config)).unwrap().mode();
+        assert_eq!(mode & 0o777, SOCKET_DIR_MODE);
+        fs::remove_dir_all(&config.runtime_dir).unwrap();
+    }
+
+    #[test]
+    fn permissive_socket_dirs_are_refused() {
+        let config = runtime_config("permissive");
+        let dir = socket_dir(&config);
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let why = BotError::from(prepare_socket_dir(&config).unwrap_err());
+        assert!(matches!(why.root(), BotError::Config(_)));
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+        fs::set_permissions(dir.parent().unwrap(), fs::Permissions::from_mode(0o777)).unwrap();
+        assert!(prepare_socket_dir(&config).is_err());
+
+        fs::set_permissions(dir.parent().unwrap(), fs::Permissions::from_mode(0o700)).unwrap();
+        prepare_socket_dir(&config).unwrap();
+        fs::remove_dir_all(&config.runtime_dir).unwrap();
+    }
+
+    fn temp_socket(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("qcorsar.{}.{}.sock", name, unique_nano()))
+    }
+
+    #[test]
+    fn socket_paths_are_namespaced_by_instance() {
+        let mut config = SocketConfig {
+            runtime_dir: "/run/user/1000".to_owned(),
+            instance: "second".to_owned(),
+            ..Default::default()
+        };
+
+        let listen = listen_path(&config);
+        assert_eq!(listen, std::path::Path::new("/run/user/1000/qcorsar/second/discord.sock"));
+        assert_eq!(peer_path(&config).parent(), listen.parent());
+
+        config.peer_path = "/srv/tg.sock".to_owned();
+        assert_eq!(peer_path(&config), std::path::Path::new("/srv/tg.sock"));
+    }
+
+    #[test]
+    fn stale_sockets_are_removed() {
+        let path = temp_socket("stale");
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        prepare_socket(&path).unwrap();
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn live_sockets_and_regular_files_are_kept() {
+        let path = temp_socket("live");
+        let listener = std::os::unix::net::UnixListener::bind(&path).unwrap();
+        let why = BotError::from(prepare_socket(&path).unwrap_err());
+        assert!(matches!(why.root(), BotError::Config(_)));
+        drop(listener);
+        std::fs::remove_file(&path).unwrap();
+
+        std::fs::write(&path, "not a socket").unwrap();
+        assert!(prepare_socket(&path).is_err());
+        assert!(path.exists());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn peer_credentials_report_the_current_user() {
+        let (left, _right) = std::os::unix::net::UnixStream::pair().unwrap();
+        let uids = allowed_uids(&SocketConfig::default());
+
+        assert_eq!(uids, vec![peer_uid(&left).unwrap()]);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use serenity::prelude::*;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use super::questionnaire::Answer;
+
+/// Запись об участнике, прошедшем регистрацию
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemberRecord {
+    pub guild_id: u64,
+    pub user_id: u64,
+    pub user_name: String,
+    pub nickname: String,
+    #[serde(default)]
+    pub answers: Vec<Answer>,
+    pub registered_at: String,
+}
+
+/// Хранилище записей об участниках
+///
+/// Записи хранятся в JSON-файле и полностью перезаписываются
+/// при каждом изменении
+#[derive(Debug)]
+pub struct MemberStorage {
+    path: PathBuf,
+    records: RwLock<Vec<MemberRecord>>,
+}
+
+impl MemberStorage {
+    /// Открытие хранилища по указанному пути
+    ///
+    /// Если файл отсутствует, хранилище создаётся пустым
+    pub fn open<P: AsRef<Path>>(path: P) -> UResult<Self> {
+        let path = path.as_ref().to_path_buf();
+        let records = if path.exists() {
+            let content = std::fs::read_to_string(&path).map_err(|why| BotError::Storage(why.into()))?;
+            serde_json::from_str(&content).map_err(|why| BotError::Storage(why.into()))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            path,
+            records: RwLock::new(records),
+        })
+    }
+
+    /// Добавление или обновление записи об участнике
+    pub fn upsert(&self, record: MemberRecord) -> UResult {
+        let mut records = match self.records.write() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Storage("RwLock on member records seems to be poisoned".into()).into()),
+        };
+        records.retain(|r| r.guild_id != record.guild_id || r.user_id != record.user_id);
+        records.push(record);
+        let content = serde_json::to_string_pretty(&*records).map_err(|why| BotError::Storage(why.into()))?;
+        std::fs::write(&self.path, content).map_err(|why| BotError::Storage(why.into()))?;
+        Ok(())
+    }
+
+    /// Поиск записи об участнике
+    pub fn get(&self, guild_id: u64, user_id: u64) -> UResult<Option<MemberRecord>> {
+        let records = match self.records.read() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Storage("RwLock on member records seems to be poisoned".into()).into()),
+        };
+        Ok(records
+            .iter()
+            .find(|r| r.guild_id == guild_id && r.user_id == user_id)
+            .cloned())
+    }
+
+    /// Все записи об участниках
+    pub fn all(&self) -> UResult<Vec<MemberRecord>> {
+        let records = match self.records.read() {
+            Ok(value) => value,
+            Err(_) => return Err(BotError::Storage("RwLock on member records seems to be poisoned".into()).into()),
+        };
+        Ok(records.clone())
+    }
+}
+
+pub struct StorageKey;
+impl TypeMapKey for StorageKey {
+    type Value = Arc<MemberStorage>;
+}
+
+/// Получение хранилища записей об участниках
+pub async fn member_storage(ctx: &Context) -> UResult<Arc<MemberStorage>> {
+    let data = ctx.data.read().await;
+    data.get::<StorageKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Member storage").into())
+}
@@ -0,0 +1,649 @@
+use std::time::Duration;
+
+use serenity::model::prelude::*;
+
+use super::auth::{AuthMode, BridgeAuth};
+use super::application::DsCommandHandler;
+use super::bridge::{self, forward_command, BridgeState, Inbound, Outgoing};
+use super::conversation::{Conversation, ConversationChannel};
+use super::discord::fake::{FakeDiscord, FAKE_DM, FAKE_THREAD};
+use super::peer::LoopbackPeer;
+use super::protocol::{self, Hello, HelloReply};
+use super::questionnaire::Question;
+use super::ratelimit::BridgeLimiter;
+use super::requests::BridgeResponse;
+use super::roles::{self, RoleRule};
+use super::transform::TelegramMarkup;
+use super::transport;
+use super::approval::ApprovalRegistry;
+use super::sessions::SessionRegistry;
+use super::storage::MemberStorage;
+use super::{rules_signup, run_signup, signup_session, SignupEnv, SignupOutcome};
+use crate::prelude::*;
+use slog::{o, Logger};
+use std::sync::Arc;
+
+const GUILD: GuildId = GuildId(100);
+const GUEST_ROLE: RoleId = RoleId(300);
+
+fn user() -> User {
+    User::default()
+}
+
+fn conversation() -> Conversation {
+    Conversation::new(ConversationChannel::Direct(FAKE_DM), user().id, Duration::from_secs(1))
+}
+
+fn rules() -> impl Iterator<Item = String> {
+    vec!["Правило 1".to_owned()].into_iter()
+}
+
+async fn signup(fake: &FakeDiscord, config: &SignupConfig) -> UResult<SignupOutcome> {
+    run_signup(fake, &conversation(), &user(), &GUILD, config, rules()).await
+}
+
+fn questions(json: &str) -> Vec<Question> {
+    serde_json::from_str(json).expect("valid questions")
+}
+
+#[tokio::test]
+async fn accepting_rules_completes_the_application() {
+    let fake = FakeDiscord::with_replies(["да", "Innri"]);
+    let outcome = signup(&fake, &SignupConfig::default()).await.unwrap();
+
+    match outcome {
+        SignupOutcome::Completed(application) => {
+            assert_eq!(application.nickname, "Innri");
+            assert_eq!(application.guild_id, GUILD);
+            assert_eq!(application.answers.len(), 1);
+            assert_eq!(application.answers[0].value, roles::RULES_ACCEPTED);
+        }
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+    assert_eq!(fake.sent_texts()[0], "Правило 1");
+}
+
+/// Регистрация от правил до итога с временным хранилищем
+async fn full_signup(fake: &FakeDiscord, config: &BotConfig, approvals: &ApprovalRegistry) -> Option<super::storage::MemberRecord> {
+    let path = std::env::temp_dir().join(format!("qcorsar.members.{}.json", unique_nano()));
+    let storage = MemberStorage::open(&path).unwrap();
+    let env = SignupEnv {
+        ops: fake,
+        config,
+        sessions: &Arc::new(SessionRegistry::default()),
+        approvals,
+        storage: &storage,
+    };
+    signup_session(&env, &user(), &GUILD, rules().collect()).await.unwrap();
+    let _ = std::fs::remove_file(&path);
+    storage.get(GUILD.0, user().id.0).unwrap()
+}
+
+#[tokio::test]
+async fn accepted_rules_finish_the_signup_immediately() {
+    let fake = FakeDiscord::with_replies(["да", "Innri"]);
+    let mut config = BotConfig::default();
+    config.signup.approval.enabled = false;
+    let record = full_signup(&fake, &config, &ApprovalRegistry::default()).await.unwrap();
+
+    assert_eq!(record.nickname, "Innri");
+    assert_eq!(fake.state().nickname.as_deref(), Some("Innri"));
+    assert_eq!(fake.state().added_roles, vec![RoleId(config.signup.member_role_id)]);
+    assert!(fake.sent_texts().last().unwrap().contains("Всё готово"));
+}
+
+#[tokio::test]
+async fn accepted_rules_wait_for_approval_when_enabled() {
+    let fake = FakeDiscord::with_replies(["да", "Innri"]);
+    let mut config = BotConfig::default();
+    config.signup.approval.enabled = true;
+    config.signup.approval.staff_channel_id = 500;
+    let approvals = ApprovalRegistry::default();
+
+    assert!(full_signup(&fake, &config, &approvals).await.is_none());
+    assert_eq!(fake.state().applications, vec![(ChannelId(500), "Innri".to_owned())]);
+    assert!(fake.state().added_roles.is_empty());
+    let application = approvals.take_by_user(GUILD, user().id).unwrap().unwrap();
+    assert_eq!(application.nickname, "Innri");
+    assert!(fake.sent_texts().last().unwrap().contains("передана администрации"));
+}
+
+#[tokio::test]
+async fn invalid_confirmation_is_asked_again() {
+    let fake = FakeDiscord::with_replies(["может быть", "yes", "Innri"]);
+    let outcome = signup(&fake, &SignupConfig::default()).await.unwrap();
+
+    assert!(matches!(outcome, SignupOutcome::Completed(_)));
+    assert!(fake
+        .sent_texts()
+        .iter()
+        .any(|text| text == "Вы можете ответить только 'Да' или 'Нет'"));
+}
+
+#[tokio::test]
+async fn declining_rules_without_guest_rules_assigns_nothing() {
+    let fake = FakeDiscord::with_replies(["нет"]);
+    let outcome = signup(&fake, &SignupConfig::default()).await.unwrap();
+
+    assert!(matches!(outcome, SignupOutcome::Declined));
+    assert!(fake.state().added_roles.is_empty());
+}
+
+#[tokio::test]
+async fn declining_rules_assigns_guest_roles() {
+    let config = SignupConfig {
+        role_rules: vec![RoleRule {
+            when: [(roles::RULES_QUESTION_ID.to_owned(), vec![roles::RULES_DECLINED.to_owned()])]
+                .into_iter()
+                .collect(),
+            add: vec![GUEST_ROLE.0],
+            remove: Vec::new(),
+        }],
+        ..Default::default()
+    };
+    let fake = FakeDiscord::with_replies(["-"]);
+    let outcome = signup(&fake, &config).await.unwrap();
+
+    assert!(matches!(outcome, SignupOutcome::Declined));
+    assert_eq!(fake.state().added_roles, vec![GUEST_ROLE]);
+}
+
+#[tokio::test]
+async fn silence_times_out() {
+    let fake = FakeDiscord::with_replies(["да"]).then_silence();
+    let why = BotError::from(signup(&fake, &SignupConfig::default()).await.unwrap_err());
+
+    assert!(matches!(why.root(), BotError::TimedOut));
+    assert!(why.ends_conversation());
+}
+
+#[tokio::test]
+async fn cancel_keyword_stops_the_dialog() {
+    let fake = FakeDiscord::with_replies(["да", "/cancel"]);
+    let why = BotError::from(signup(&fake, &SignupConfig::default()).await.unwrap_err());
+
+    assert!(matches!(why.root(), BotError::UserInput(UserInputError::Cancelled)));
+}
+
+#[tokio::test]
+async fn leaving_the_guild_stops_the_dialog() {
+    let fake = FakeDiscord::with_replies(["да"]);
+    fake.state().leaves_after = Some(1);
+    let outcome = signup(&fake, &SignupConfig::default()).await.unwrap();
+
+    assert!(matches!(outcome, SignupOutcome::Left));
+}
+
+#[tokio::test]
+async fn questionnaire_validates_and_branches() {
+    let config = SignupConfig {
+        questions: questions(
+            r#"[
+                {"id": "age", "prompt": "Возраст?", "type": "number", "min": 14},
+                {"id": "class", "prompt": "Класс?", "type": "choice", "options": ["Воин", "Маг"],
+                 "branches": {"Воин": "end"}},
+                {"id": "school", "prompt": "Школа магии?", "type": "text"}
+            ]"#,
+        ),
+        ..Default::default()
+    };
+    let fake = FakeDiscord::with_replies(["да", "Innri", "десять", "12", "20", "3", "воин"]);
+    let outcome = signup(&fake, &config).await.unwrap();
+
+    let application = match outcome {
+        SignupOutcome::Completed(application) => application,
+        other => panic!("unexpected outcome: {:?}", other),
+    };
+    let values: Vec<_> = application.answers.iter().map(|a| a.value.as_str()).collect();
+    assert_eq!(values, vec![roles::RULES_ACCEPTED, "20", "Воин"]);
+    assert!(fake
+        .sent_texts()
+        .iter()
+        .any(|text| text == "Выберите один из предложенных вариантов"));
+}
+
+#[test]
+fn nicknames_are_trimmed_and_capped() {
+    assert_eq!(super::validate_nickname("  Innri "), Ok("Innri".to_owned()));
+    assert_eq!(super::validate_nickname(&"Н".repeat(32)), Ok("Н".repeat(32)));
+    assert!(super::validate_nickname(&"Н".repeat(33)).is_err());
+    assert!(super::validate_nickname("   ").is_err());
+}
+
+#[tokio::test]
+async fn overlong_nicknames_are_asked_again() {
+    let long = "Н".repeat(33);
+    let fake = FakeDiscord::with_replies(["да", long.as_str(), "Innri"]);
+    let outcome = signup(&fake, &SignupConfig::default()).await.unwrap();
+
+    match outcome {
+        SignupOutcome::Completed(application) => assert_eq!(application.nickname, "Innri"),
+        other => panic!("unexpected outcome: {:?}", other),
+    }
+    assert!(fake.sent_texts().iter().any(|text| text.contains("от 1 до 32 символов")));
+}
+
+#[tokio::test]
+async fn closed_direct_messages_fall_back_to_a_thread() {
+    let fake = FakeDiscord::default();
+    fake.state().dm_closed = true;
+    let channel = ConversationChannel::open(&fake, &user(), &GUILD, 500, "Привет").await.unwrap();
+
+    assert!(matches!(channel, ConversationChannel::Thread(_)));
+    assert_eq!(channel.id(), FAKE_THREAD);
+    assert_eq!(fake.state().sent, vec![(FAKE_THREAD, "Привет".to_owned())]);
+
+    channel.close(&fake).await.unwrap();
+    assert_eq!(fake.state().closed_threads, vec![FAKE_THREAD]);
+}
+
+/// Канал, в котором вызвана команда `rules`
+const COMMAND_CHANNEL: ChannelId = ChannelId(700);
+
+/// Команда `rules` с временным хранилищем
+async fn rules_command(fake: &FakeDiscord, config: &BotConfig) -> Result<(), BotError> {
+    let path = std::env::temp_dir().join(format!("qcorsar.members.{}.json", unique_nano()));
+    let storage = MemberStorage::open(&path).unwrap();
+    let env = SignupEnv {
+        ops: fake,
+        config,
+        sessions: &Arc::new(SessionRegistry::default()),
+        approvals: &ApprovalRegistry::default(),
+        storage: &storage,
+    };
+    let result = rules_signup(&env, COMMAND_CHANNEL, &user(), &GUILD, Ok(rules().collect())).await;
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+#[tokio::test]
+async fn rules_command_falls_back_to_a_thread_when_direct_messages_are_closed() {
+    let fake = FakeDiscord::with_replies(["да", "Innri"]);
+    fake.state().dm_closed = true;
+    let mut config = BotConfig::default();
+    config.signup.onboarding_channel_id = 500;
+    rules_command(&fake, &config).await.unwrap();
+
+    let sent = fake.state().sent.clone();
+    assert_eq!(sent[0].0, COMMAND_CHANNEL);
+    assert!(sent[0].1.starts_with("Отправляю тебе свод правил"));
+    assert!(sent[1..].iter().all(|(channel, _)| *channel == FAKE_THREAD));
+    assert_eq!(fake.state().nickname.as_deref(), Some("Innri"));
+    assert_eq!(fake.state().closed_threads, vec![FAKE_THREAD]);
+}
+
+#[tokio::test]
+async fn rules_command_reports_failures_in_the_channel_when_direct_messages_are_closed() {
+    let fake = FakeDiscord::default();
+    fake.state().dm_closed = true;
+    let why = rules_command(&fake, &BotConfig::default()).await.unwrap_err();
+
+    assert!(why.is_dm_closed());
+    let sent = fake.state().sent.clone();
+    assert_eq!(sent.len(), 2);
+    assert_eq!(sent[1], (COMMAND_CHANNEL, why.user_message()));
+}
+
+#[tokio::test]
+async fn closed_direct_messages_without_onboarding_channel_fail() {
+    let fake = FakeDiscord::default();
+    fake.state().dm_closed = true;
+    let why = ConversationChannel::open(&fake, &user(), &GUILD, 0, "Привет")
+        .await
+        .unwrap_err();
+
+    assert!(BotError::from(why).is_dm_closed());
+}
+
+#[test]
+fn forwarded_messages_reach_the_bridge_peer() {
+    let peer = LoopbackPeer::spawn().unwrap();
+    let state = BridgeState::default();
+    let origin = state.origin_for(MessageId(42));
+
+    let cmd = forward_command(origin.clone(), "Innri".to_owned(), "Привет".to_owned());
+    peer.sender().send(cmd).unwrap();
+
+    match peer.recv().map(|cmd| cmd.kind) {
+        Some(CommandKind::ForwardMessage { from, to: _, content }) => {
+            assert_eq!(from.server, origin);
+            assert_eq!(from.name, "Innri");
+            assert_eq!(content, "Привет");
+            assert!(state.has_traversed(&from.server));
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+}
+
+#[test]
+fn outgoing_messages_reach_the_peer_with_formatted_authors() {
+    let peer = LoopbackPeer::spawn().unwrap();
+    let config = BridgeConfig {
+        channel_id: 10,
+        ..markdown_bridge(&[])
+    };
+    let state = BridgeState::default();
+    let limiter = BridgeLimiter::new(&BridgeLimitsConfig::default());
+    let outgoing = Outgoing {
+        channel_id: ChannelId(10),
+        author_id: UserId(1),
+        author_bot: false,
+        own: false,
+    };
+    let sender = peer.sender();
+
+    for (nickname, content) in [(Some("Инри".to_owned()), "**привет** <:pepe:1>"), (None, "@everyone ок")] {
+        bridge::check_outgoing(&outgoing, &config, &state, &limiter).unwrap();
+        let cmd = bridge::outgoing_command(&state, &config, MessageId(42), "innri", nickname, content);
+        sender.send(cmd).unwrap();
+    }
+
+    let expected = [("Инри (innri)", "*привет* :pepe:"), ("innri", "everyone ок")];
+    for (name, content) in expected {
+        match peer.recv().map(|cmd| cmd.kind) {
+            Some(CommandKind::ForwardMessage { from, to, content: received }) => {
+                assert_eq!(from.server, state.origin_for(MessageId(42)));
+                assert_eq!(from.name, name);
+                assert_eq!(to.server, "telegram_server_id");
+                assert_eq!(to.name, "");
+                assert_eq!(received, content);
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn incoming_commands_are_dispatched_in_order() {
+    let peer = LoopbackPeer::spawn().unwrap();
+    let sender = peer.sender();
+    for content in ["первое", "второе"] {
+        let cmd = forward_command("telegram_server_id".to_owned(), "tg".to_owned(), content.to_owned());
+        sender.send(cmd).unwrap();
+    }
+
+    for expected in ["первое", "второе"] {
+        match peer.recv().map(|cmd| cmd.kind) {
+            Some(CommandKind::ForwardMessage { from, to: _, content }) => {
+                assert_eq!(content, expected);
+                assert!(!BridgeState::default().has_traversed(&from.server));
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+}
+
+#[test]
+fn bound_sockets_are_accessible_only_to_the_owner() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let peer = LoopbackPeer::spawn().unwrap();
+    let mode = std::fs::metadata(&peer.config.socket.listen_path)
+        .unwrap()
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+}
+
+fn bridge_auth(secret: &str, mode: AuthMode) -> BridgeAuth {
+    BridgeAuth::new(&BridgeAuthConfig {
+        secret: secret.to_owned(),
+        mode,
+        max_age_secs: 300,
+    })
+    .unwrap()
+}
+
+fn assert_round_trip(peer: &LoopbackPeer) {
+    let cmd = forward_command("discord:x:1".to_owned(), "Innri".to_owned(), "Привет".to_owned());
+    peer.sender().send(cmd).unwrap();
+
+    match peer.recv().map(|cmd| cmd.kind) {
+        Some(CommandKind::ForwardMessage { from, to: _, content }) => {
+            assert_eq!(from.name, "Innri");
+            assert_eq!(content, "Привет");
+        }
+        other => panic!("unexpected command: {:?}", other),
+    }
+}
+
+#[test]
+fn commands_travel_over_tcp() {
+    let peer = LoopbackPeer::spawn_tcp(TlsConfig::default()).unwrap();
+    assert_round_trip(&peer);
+}
+
+#[test]
+fn commands_travel_over_tls() {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_owned()]).unwrap();
+    let dir = std::env::temp_dir();
+    let cert_path = dir.join(format!("qcorsar.{}.crt", unique_nano()));
+    let key_path = dir.join(format!("qcorsar.{}.key", unique_nano()));
+    std::fs::write(&cert_path, cert.serialize_pem().unwrap()).unwrap();
+    std::fs::write(&key_path, cert.serialize_private_key_pem()).unwrap();
+
+    let tls = TlsConfig {
+        enabled: true,
+        cert_path: cert_path.to_string_lossy().into_owned(),
+        key_path: key_path.to_string_lossy().into_owned(),
+        ca_path: cert_path.to_string_lossy().into_owned(),
+        server_name: "localhost".to_owned(),
+        client_auth: true,
+    };
+    let peer = LoopbackPeer::spawn_tcp(tls).unwrap();
+    assert_round_trip(&peer);
+
+    let _ = std::fs::remove_file(cert_path);
+    let _ = std::fs::remove_file(key_path);
+}
+
+#[test]
+fn open_tcp_listeners_require_signatures_or_client_certificates() {
+    let mut config = BridgeConfig::default();
+    config.transport.listen_addr = "0.0.0.0:7451".to_owned();
+    assert!(transport::check_exposure(&config).is_err());
+
+    config.auth = BridgeAuthConfig {
+        secret: "secret".to_owned(),
+        mode: AuthMode::Required,
+        ..Default::default()
+    };
+    assert!(transport::check_exposure(&config).is_ok());
+
+    config.auth = BridgeAuthConfig::default();
+    config.transport.tls.enabled = true;
+    config.transport.tls.client_auth = true;
+    assert!(transport::check_exposure(&config).is_ok());
+
+    config.transport.tls = TlsConfig::default();
+    config.transport.listen_addr = "127.0.0.1:7451".to_owned();
+    assert!(transport::check_exposure(&config).is_ok());
+}
+
+#[test]
+fn peers_one_version_behind_are_served() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let previous = match protocol::current_version().unwrap().checked_sub(1) {
+        Some(previous) => previous,
+        None => return,
+    };
+    let peer = LoopbackPeer::spawn_tcp(TlsConfig::default()).unwrap();
+    let mut stream = std::net::TcpStream::connect(&peer.config.transport.peer_addr).unwrap();
+    let hello = Hello { versions: vec![previous] };
+    stream
+        .write_all(format!("{}\n", serde_json::to_string(&hello).unwrap()).as_bytes())
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).unwrap();
+    let reply: HelloReply = serde_json::from_str(line.trim_end()).unwrap();
+    assert_eq!(reply.version, Some(previous));
+
+    let fake = Arc::new(FakeDiscord::default());
+    let inbound = Inbound {
+        config: BridgeConfig {
+            channel_id: 10,
+            ..Default::default()
+        },
+        state: Arc::default(),
+        auth: Arc::new(bridge_auth("secret", AuthMode::Required)),
+        limiter: Arc::new(BridgeLimiter::new(&BridgeLimitsConfig::default())),
+        metrics: Arc::default(),
+        discord: fake.clone(),
+    };
+    let metrics = inbound.metrics.clone();
+    let logger = Logger::root(slog::Discard, o!());
+    let handler = DsCommandHandler::new(logger, Arc::new(inbound), tokio::runtime::Runtime::new().unwrap());
+    let peer_auth = bridge_auth("secret", AuthMode::Required);
+    let stamped = |version: u64, content: &str| {
+        let mut cmd = forward_command("telegram_server_id".to_owned(), "Innri".to_owned(), content.to_owned());
+        protocol::stamp(&mut cmd, version).unwrap();
+        peer_auth.sign(&mut cmd);
+        cmd
+    };
+    let _ = handler.forward_message(stamped(previous, "старая версия"));
+    let _ = handler.forward_message(stamped(previous + 2, "будущая версия"));
+
+    assert_eq!(
+        fake.state().bridged,
+        vec![(ChannelId(10), "Innri".to_owned(), "старая версия".to_owned())]
+    );
+    assert_eq!(metrics.get("bridge.protocol.unsupported"), 1);
+}
+
+#[test]
+fn peers_without_a_common_version_are_rejected() {
+    use std::io::{BufRead, BufReader, Write};
+
+    let peer = LoopbackPeer::spawn_tcp(TlsConfig::default()).unwrap();
+    let mut stream = std::net::TcpStream::connect(&peer.config.transport.peer_addr).unwrap();
+    let hello = Hello {
+        versions: vec![protocol::current_version().unwrap() + 1],
+    };
+    stream
+        .write_all(format!("{}\n", serde_json::to_string(&hello).unwrap()).as_bytes())
+        .unwrap();
+
+    let mut line = String::new();
+    BufReader::new(&stream).read_line(&mut line).unwrap();
+    let reply: HelloReply = serde_json::from_str(line.trim_end()).unwrap();
+    assert_eq!(reply.version, None);
+    assert_eq!(reply.supported, protocol::supported_versions());
+}
+
+/// Отправка одной строки собеседнику и чтение ответа на неё
+///
+/// По TCP сначала отправляется приветствие, по сокету Unix
+/// строка передаётся сразу, как это делают отправители qcproto
+fn raw_exchange(peer: &LoopbackPeer, frame: &str) -> String {
+    match peer.config.transport.kind {
+        transport::TransportKind::Tcp => {
+            let stream = std::net::TcpStream::connect(&peer.config.transport.peer_addr).unwrap();
+            let hello = serde_json::to_string(&Hello::new()).unwrap();
+            read_reply(stream, &format!("{}\n{}\n", hello, frame), 2)
+        }
+        transport::TransportKind::Unix => {
+            let stream = std::os::unix::net::UnixStream::connect(&peer.config.socket.listen_path).unwrap();
+            read_reply(stream, &format!("{}\n", frame), 1)
+        }
+    }
+}
+
+fn read_reply<S: std::io::Read + std::io::Write>(mut stream: S, frames: &str, replies: usize) -> String {
+    use std::io::{BufRead, BufReader};
+
+    stream.write_all(frames.as_bytes()).unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    for _ in 0..replies {
+        line.clear();
+        reader.read_line(&mut line).unwrap();
+    }
+    line
+}
+
+fn assert_requests_answered(peer: &LoopbackPeer) {
+    let reply = raw_exchange(peer, r#"{"request": "status"}"#);
+    match serde_json::from_str(reply.trim_end()).unwrap() {
+        BridgeResponse::Status { bot_version, protocol_versions, .. } => {
+            assert_eq!(bot_version, "loopback");
+            assert_eq!(protocol_versions, protocol::supported_versions());
+        }
+        other => panic!("unexpected response: {:?}", other),
+    }
+
+    let reply = raw_exchange(peer, r#"{"request": "teleport", "to": "Тортуга"}"#);
+    let response: BridgeResponse = serde_json::from_str(reply.trim_end()).unwrap();
+    assert_eq!(
+        response,
+        BridgeResponse::Unsupported {
+            request: "teleport".to_owned()
+        }
+    );
+
+    let reply = raw_exchange(peer, r#"{"kind": {"Teleport": {"to": "Тортуга"}}, "sender_bot_family": "Telegram", "protocol_version": 1}"#);
+    let response: BridgeResponse = serde_json::from_str(reply.trim_end()).unwrap();
+    assert_eq!(
+        response,
+        BridgeResponse::UnsupportedCommand {
+            command: "Teleport".to_owned()
+        }
+    );
+}
+
+#[test]
+fn bridge_requests_are_answered() {
+    assert_requests_answered(&LoopbackPeer::spawn_tcp(TlsConfig::default()).unwrap());
+}
+
+#[test]
+fn bridge_requests_are_answered_over_unix_sockets() {
+    assert_requests_answered(&LoopbackPeer::spawn().unwrap());
+}
+
+fn markdown_bridge(words: &[&str]) -> BridgeConfig {
+    BridgeConfig {
+        word_filter: words.iter().map(|w| w.to_string()).collect(),
+        telegram_markup: TelegramMarkup::MarkdownV2,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn bridge_commands_are_published_through_the_command_handler() {
+    let fake = Arc::new(FakeDiscord::default());
+    let inbound = Inbound {
+        config: BridgeConfig {
+            channel_id: 10,
+            ..Default::default()
+        },
+        state: Arc::default(),
+        auth: Arc::new(bridge_auth("secret", AuthMode::Required)),
+        limiter: Arc::new(BridgeLimiter::new(&BridgeLimitsConfig::default())),
+        metrics: Arc::default(),
+        discord: fake.clone(),
+    };
+    let (state, metrics) = (inbound.state.clone(), inbound.metrics.clone());
+    let logger = Logger::root(slog::Discard, o!());
+    let handler = DsCommandHandler::new(logger, Arc::new(inbound), tokio::runtime::Runtime::new().unwrap());
+
+    let peer_auth = bridge_auth("secret", AuthMode::Required);
+    let signed = |origin: String, content: &str| {
+        let mut cmd = forward_command(origin, "Innri".to_owned(), content.to_owned());
+        peer_auth.sign(&mut cmd);
+        cmd
+    };
+    handler.forward_message(signed("telegram_server_id".to_owned(), "*привет* @everyone")).unwrap();
+    let unsigned = forward_command("telegram_server_id".to_owned(), "Innri".to_owned(), "без подписи".to_owned());
+    handler.forward_message(unsigned).unwrap();
+    handler.forward_message(signed(state.origin_for(MessageId(1)), "петля")).unwrap();
+    state.pause();
+    handler.forward_message(signed("telegram_server_id".to_owned(), "на паузе")).unwrap();
+
+    assert_eq!(
+        fake.state().bridged,
+        vec![(ChannelId(10), "Innri".to_owned(), r"\*привет\* everyone".to_owned())]
+    );
+    assert_eq!(metrics.get("bridge.auth.unsigned"), 1);
+    assert_eq!(metrics.get("bridge.loop_dropped"), 1);
+}
@@ -0,0 +1,427 @@
+use crate::prelude::*;
+use serenity::{
+    model::prelude::*,
+    prelude::*,
+    utils::{content_safe, ContentSafeOptions},
+};
+use serde::{Deserialize, Serialize};
+
+/// Соответствие маркеров разметки Discord и Telegram
+///
+/// При обратном преобразовании используется первая пара
+/// с подходящим маркером Telegram
+const MARKER_PAIRS: &[(&str, &str)] = &[
+    ("**", "*"),
+    ("*", "_"),
+    ("_", "_"),
+    ("__", "__"),
+    ("~~", "~"),
+    ("||", "||"),
+];
+
+/// Символы, требующие экранирования в Telegram MarkdownV2
+const TELEGRAM_SPECIAL: &str = "_*[]()~`>#+-=|{}.!\\";
+/// Символы разметки Discord, требующие экранирования
+const DISCORD_SPECIAL: &str = "*_~`|\\";
+
+/// Разметка сообщений на стороне Telegram
+///
+/// `plain` - обычный текст, `markdown_v2` - Telegram
+/// MarkdownV2. Бот Telegram должен отправлять и показывать
+/// сообщения моста в той же разметке, поэтому MarkdownV2
+/// включается только явно
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TelegramMarkup {
+    #[default]
+    Plain,
+    MarkdownV2,
+}
+
+/// Диалект разметки сообщений
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    Discord,
+    /// Telegram MarkdownV2
+    Telegram,
+}
+
+impl Markup {
+    /// Маркеры форматирования в порядке убывания длины
+    fn markers(self) -> &'static [&'static str] {
+        match self {
+            Self::Discord => &["||", "**", "__", "~~", "*", "_"],
+            Self::Telegram => &["||", "__", "*", "_", "~"],
+        }
+    }
+
+    fn escape(self, text: &str) -> String {
+        let special = match self {
+            Self::Discord => DISCORD_SPECIAL,
+            Self::Telegram => TELEGRAM_SPECIAL,
+        };
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if special.contains(c) {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    fn escape_code(self, code: &str) -> String {
+        match self {
+            Self::Discord => code.to_owned(),
+            Self::Telegram => code.replace('\\', "\\\\").replace('`', "\\`"),
+        }
+    }
+
+    /// Перевод маркера этого диалекта в маркер другого
+    fn translate(self, marker: &str, target: Markup) -> &'static str {
+        let pair = match self {
+            Self::Discord => MARKER_PAIRS.iter().find(|(ds, _)| *ds == marker),
+            Self::Telegram => MARKER_PAIRS.iter().find(|(_, tg)| *tg == marker),
+        };
+        match (pair, target) {
+            (Some((ds, _)), Markup::Discord) => ds,
+            (Some((_, tg)), Markup::Telegram) => tg,
+            (None, _) => "",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Marker(&'static str),
+    Code { fence: &'static str, body: String },
+}
+
+/// Разбиение текста на фрагменты разметки
+///
+/// Экранированные символы, ссылки и подчёркивания внутри
+/// слов считаются обычным текстом
+fn tokenize(content: &str, markup: Markup) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut rest = content;
+    let mut prev: Option<char> = None;
+
+    while let Some(c) = rest.chars().next() {
+        if c == '\\' {
+            if let Some(escaped) = rest.chars().nth(1) {
+                text.push(escaped);
+                prev = Some(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+        }
+        if rest.starts_with("http://") || rest.starts_with("https://") {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            text.push_str(&rest[..end]);
+            prev = rest[..end].chars().last();
+            rest = &rest[end..];
+            continue;
+        }
+        if let Some(fence) = ["```", "`"].into_iter().find(|f| rest.starts_with(*f)) {
+            if let Some(end) = rest[fence.len()..].find(fence) {
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                let body = &rest[fence.len()..fence.len() + end];
+                tokens.push(Token::Code { fence, body: body.to_owned() });
+                rest = &rest[fence.len() * 2 + end..];
+                prev = Some('`');
+                continue;
+            }
+        }
+        if let Some(marker) = markup.markers().iter().copied().find(|m| rest.starts_with(*m)) {
+            let next = rest[marker.len()..].chars().next();
+            let intraword = marker == "_"
+                && prev.is_some_and(char::is_alphanumeric)
+                && next.is_some_and(char::is_alphanumeric);
+            if !intraword {
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(Token::Marker(marker));
+                rest = &rest[marker.len()..];
+                prev = marker.chars().last();
+                continue;
+            }
+        }
+        text.push(c);
+        prev = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    tokens
+}
+
+/// Поиск парных маркеров
+///
+/// Маркер без закрывающей пары выводится как обычный текст
+fn pair_markers(tokens: &[Token]) -> Vec<bool> {
+    let mut paired = vec![false; tokens.len()];
+    for i in 0..tokens.len() {
+        let marker = match &tokens[i] {
+            Token::Marker(m) if !paired[i] => *m,
+            _ => continue,
+        };
+        let closing = (i + 1..tokens.len())
+            .find(|&j| !paired[j] && matches!(tokens[j], Token::Marker(m) if m == marker));
+        if let Some(j) = closing {
+            paired[i] = true;
+            paired[j] = true;
+        }
+    }
+    paired
+}
+
+/// Фильтр нежелательных слов
+///
+/// Слова сравниваются целиком без учёта регистра и
+/// заменяются звёздочками. Запись может состоять из
+/// нескольких слов, в тексте они могут разделяться любыми
+/// символами, кроме букв и цифр. Фильтр применяется к
+/// каждому фрагменту текста и коду отдельно, поэтому
+/// запись, разделённая маркерами разметки, не находится
+#[derive(Debug, Default)]
+pub struct WordFilter {
+    entries: Vec<Vec<String>>,
+}
+
+/// Границы слов (последовательностей букв и цифр) в тексте
+fn word_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+impl WordFilter {
+    pub fn new(words: &[String]) -> Self {
+        let entries = words
+            .iter()
+            .map(|entry| {
+                let entry = entry.to_lowercase();
+                word_spans(&entry)
+                    .into_iter()
+                    .map(|(start, end)| entry[start..end].to_owned())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|entry| !entry.is_empty())
+            .collect();
+        Self { entries }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        if self.entries.is_empty() {
+            return text.to_owned();
+        }
+        let spans = word_spans(text);
+        let words: Vec<String> = spans.iter().map(|&(start, end)| text[start..end].to_lowercase()).collect();
+        let mut masked = vec![false; spans.len()];
+        for i in 0..words.len() {
+            for entry in &self.entries {
+                if words[i..].starts_with(entry) {
+                    masked[i..i + entry.len()].iter_mut().for_each(|m| *m = true);
+                }
+            }
+        }
+
+        let mut result = String::with_capacity(text.len());
+        let mut last = 0;
+        for (&(start, end), masked) in spans.iter().zip(masked) {
+            if !masked {
+                continue;
+            }
+            result.push_str(&text[last..start]);
+            result.extend(std::iter::repeat_n('*', text[start..end].chars().count()));
+            last = end;
+        }
+        result.push_str(&text[last..]);
+        result
+    }
+}
+
+/// Преобразование разметки сообщения между диалектами
+pub fn convert(content: &str, from: Markup, to: Markup, filter: &WordFilter) -> String {
+    let tokens = tokenize(content, from);
+    let paired = pair_markers(&tokens);
+    let mut result = String::with_capacity(content.len());
+    for (token, paired) in tokens.iter().zip(paired) {
+        match token {
+            Token::Text(text) => result.push_str(&to.escape(&filter.apply(text))),
+            Token::Marker(marker) if paired => result.push_str(from.translate(marker, to)),
+            Token::Marker(marker) => result.push_str(&to.escape(marker)),
+            Token::Code { fence, body } => {
+                result.push_str(fence);
+                result.push_str(&to.escape_code(&filter.apply(body)));
+                result.push_str(fence);
+            }
+        }
+    }
+    result
+}
+
+/// Удаление массовых упоминаний
+pub fn strip_mass_mentions(content: &str) -> String {
+    content.replace("@everyone", "everyone").replace("@here", "here")
+}
+
+/// Замена пользовательских эмодзи Discord (`<:name:id>`) на `:name:`
+pub fn replace_custom_emoji(content: &str) -> String {
+    let mut result = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find('<') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let emoji = rest.find('>').and_then(|end| {
+            let inner = &rest[1..end];
+            let inner = inner.strip_prefix('a').unwrap_or(inner);
+            let mut parts = inner.strip_prefix(':')?.split(':');
+            let name = parts.next().filter(|n| !n.is_empty())?;
+            let id = parts.next().filter(|id| id.chars().all(|c| c.is_ascii_digit()))?;
+            match parts.next() {
+                None if !id.is_empty() => Some((format!(":{}:", name), end)),
+                _ => None,
+            }
+        });
+        match emoji {
+            Some((name, end)) => {
+                result.push_str(&name);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                result.push('<');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Текст сообщения Discord с упоминаниями, заменёнными названиями
+///
+/// Упоминания пользователей, ролей и каналов заменяются
+/// их названиями по кэшу. Дальнейшее преобразование для
+/// Telegram выполняет [`discord_to_telegram`]
+pub fn safe_content(ctx: &Context, msg: &Message) -> String {
+    let options = ContentSafeOptions::default()
+        .show_discriminator(false)
+        .clean_everyone(false)
+        .clean_here(false);
+    content_safe(&ctx.cache, &msg.content, &options, &msg.mentions)
+}
+
+/// Преобразование текста сообщения Discord для Telegram
+///
+/// Пользовательские эмодзи заменяются их именами,
+/// массовые упоминания удаляются. В разметке
+/// `markdown_v2` форматирование переводится в Telegram
+/// MarkdownV2, иначе текст передаётся как есть
+pub fn discord_to_telegram(content: &str, config: &BridgeConfig) -> String {
+    let content = strip_mass_mentions(&replace_custom_emoji(content));
+    let filter = WordFilter::new(&config.word_filter);
+    match config.telegram_markup {
+        TelegramMarkup::Plain => filter.apply(&content),
+        TelegramMarkup::MarkdownV2 => convert(&content, Markup::Discord, Markup::Telegram, &filter),
+    }
+}
+
+/// Подготовка сообщения из Telegram к публикации в Discord
+///
+/// Обычный текст экранируется, чтобы он не был
+/// истолкован как разметка Discord
+pub fn to_discord(content: &str, config: &BridgeConfig) -> String {
+    let content = strip_mass_mentions(content);
+    let filter = WordFilter::new(&config.word_filter);
+    match config.telegram_markup {
+        TelegramMarkup::Plain => Markup::Discord.escape(&filter.apply(&content)),
+        TelegramMarkup::MarkdownV2 => convert(&content, Markup::Telegram, Markup::Discord, &filter),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markdown_bridge(words: &[&str]) -> BridgeConfig {
+        BridgeConfig {
+            word_filter: words.iter().map(|w| w.to_string()).collect(),
+            telegram_markup: TelegramMarkup::MarkdownV2,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn markup_is_translated_between_dialects() {
+        let none = WordFilter::default();
+        let to_tg = |content: &str| convert(content, Markup::Discord, Markup::Telegram, &none);
+        assert_eq!(to_tg("**жирный** и *курсив*"), "*жирный* и _курсив_");
+        assert_eq!(to_tg("snake_case и https://example.com/a_b"), r"snake\_case и https://example\.com/a\_b");
+        assert_eq!(to_tg(r"\*звёздочки\*"), r"\*звёздочки\*");
+        assert_eq!(to_tg("`a*b` и ```x_y```"), "`a*b` и ```x_y```");
+
+        let to_ds = |content: &str| convert(content, Markup::Telegram, Markup::Discord, &none);
+        assert_eq!(to_ds("*жирный* и ~зачёркнутый~"), "**жирный** и ~~зачёркнутый~~");
+    }
+
+    #[test]
+    fn unbalanced_markers_are_escaped() {
+        let none = WordFilter::default();
+        let to_tg = |content: &str| convert(content, Markup::Discord, Markup::Telegram, &none);
+        assert_eq!(to_tg("**жирный"), r"\*\*жирный");
+        assert_eq!(to_tg("*a* *b"), r"_a_ \*b");
+    }
+
+    #[test]
+    fn custom_emoji_are_replaced_by_names() {
+        assert_eq!(
+            replace_custom_emoji("<:pepe:123> <a:dance:456> <@!1> <:broken:>"),
+            ":pepe: :dance: <@!1> <:broken:>"
+        );
+    }
+
+    #[test]
+    fn markdown_is_used_only_when_configured() {
+        let plain = BridgeConfig::default();
+        assert_eq!(discord_to_telegram("**hi** @everyone <:pepe:1>", &plain), "**hi** everyone :pepe:");
+        assert_eq!(to_discord("*не жирный* @here", &plain), r"\*не жирный\* here");
+
+        let markdown = markdown_bridge(&[]);
+        assert_eq!(discord_to_telegram("**hi** <:pepe:1>", &markdown), r"*hi* :pepe:");
+        assert_eq!(to_discord("*жирный*", &markdown), "**жирный**");
+    }
+
+    #[test]
+    fn word_filter_masks_whole_words_and_phrases() {
+        let filter = WordFilter::new(&["Бранное".to_owned(), "very bad".to_owned()]);
+        assert_eq!(
+            filter.apply("бранное слово, бранноеслово, VERY, bad! very-good"),
+            "******* слово, бранноеслово, ****, ***! very-good"
+        );
+        assert_eq!(
+            convert("`бранное` **very bad**", Markup::Discord, Markup::Telegram, &filter),
+            "`*******` *\\*\\*\\*\\* \\*\\*\\**"
+        );
+        assert_eq!(discord_to_telegram("very bad", &markdown_bridge(&["very bad"])), r"\*\*\*\* \*\*\*");
+    }
+}
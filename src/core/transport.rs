@@ -0,0 +1,600 @@
+use crate::prelude::*;
+use rustls::server::AllowAnyAuthenticatedClient;
+use rustls::{Certificate, ClientConfig, ClientConnection, PrivateKey, RootCertStore, ServerConfig, ServerConnection, ServerName, StreamOwned};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use slog::Logger;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::auth::{AuthMode, BridgeAuth};
+use super::metrics::Metrics;
+use super::protocol;
+use super::requests::{self, BridgeResponse, RequestHandler};
+
+/// Максимальный размер одной команды в байтах
+const MAX_FRAME_LEN: u64 = 64 * 1024;
+/// Время ожидания чтения и записи по TCP
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+/// Время ожидания подключения к собеседнику по TCP
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Транспорт моста
+///
+/// `unix` - сокеты Unix (см. [`super::socket`]), `tcp` -
+/// TCP с необязательным TLS для запуска половин моста на
+/// разных машинах
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    #[default]
+    Unix,
+    Tcp,
+}
+
+fn read_pem(path: &str) -> UResult<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .map_err(|why| BotError::Config(format!("Could not open {}: {}", path, why)).into())
+}
+
+fn load_certs(path: &str) -> UResult<Vec<Certificate>> {
+    let certs = rustls_pemfile::certs(&mut read_pem(path)?)?;
+    if certs.is_empty() {
+        return Err(BotError::Config(format!("No certificates found in {}", path)).into());
+    }
+    Ok(certs.into_iter().map(Certificate).collect())
+}
+
+fn load_key(path: &str) -> UResult<PrivateKey> {
+    let mut keys = rustls_pemfile::pkcs8_private_keys(&mut read_pem(path)?)?;
+    if keys.is_empty() {
+        keys = rustls_pemfile::rsa_private_keys(&mut read_pem(path)?)?;
+    }
+    keys.into_iter()
+        .next()
+        .map(PrivateKey)
+        .ok_or(BotError::Config(format!("No private key found in {}", path)).into())
+}
+
+fn load_roots(path: &str) -> UResult<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(&cert)
+            .map_err(|why| BotError::Config(format!("Invalid CA certificate: {}", why)))?;
+    }
+    Ok(roots)
+}
+
+/// Настройки TLS сервера команд
+///
+/// При `client_auth` собеседник должен предъявить
+/// сертификат, подписанный `ca_path`
+pub fn server_tls(config: &TlsConfig) -> UResult<Arc<ServerConfig>> {
+    let builder = ServerConfig::builder().with_safe_defaults();
+    let builder = if config.client_auth {
+        if config.ca_path.is_empty() {
+            return Err(BotError::Config("TLS client authentication requires a CA certificate".to_owned()).into());
+        }
+        builder.with_client_cert_verifier(AllowAnyAuthenticatedClient::new(load_roots(&config.ca_path)?))
+    } else {
+        builder.with_no_client_auth()
+    };
+    let tls = builder
+        .with_single_cert(load_certs(&config.cert_path)?, load_key(&config.key_path)?)
+        .map_err(|why| BotError::Config(format!("Invalid TLS certificate: {}", why)))?;
+    Ok(Arc::new(tls))
+}
+
+/// Настройки TLS отправителя команд
+///
+/// Сертификат собеседника проверяется по `ca_path`, а если
+/// он не задан - по общедоступным корневым сертификатам.
+/// При `client_auth` собеседнику предъявляется `cert_path`
+pub fn client_tls(config: &TlsConfig) -> UResult<Arc<ClientConfig>> {
+    let roots = if config.ca_path.is_empty() {
+        let mut roots = RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+        }));
+        roots
+    } else {
+        load_roots(&config.ca_path)?
+    };
+    let builder = ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+    let tls = if config.client_auth {
+        builder
+            .with_single_cert(load_certs(&config.cert_path)?, load_key(&config.key_path)?)
+            .map_err(|why| BotError::Config(format!("Invalid TLS client certificate: {}", why)))?
+    } else {
+        builder.with_no_client_auth()
+    };
+    Ok(Arc::new(tls))
+}
+
+/// Проверка защиты сервера команд на TCP
+///
+/// Сервер, доступный не только с localhost, запускается,
+/// только если команды обязаны быть подписаны (режим
+/// `required`) или собеседник предъявляет сертификат
+/// клиента. Иначе любой узел сети мог бы писать в группу
+/// и читать справочник участников
+pub fn check_exposure(config: &BridgeConfig) -> UResult {
+    let tcp = &config.transport;
+    let addrs: Vec<SocketAddr> = tcp
+        .listen_addr
+        .to_socket_addrs()
+        .map_err(|why| BotError::Config(format!("Invalid listen address {}: {}", tcp.listen_addr, why)))?
+        .collect();
+    let loopback = !addrs.is_empty() && addrs.iter().all(|addr| addr.ip().is_loopback());
+    let signed = config.auth.mode == AuthMode::Required && !config.auth.secret.is_empty();
+    let client_certs = tcp.tls.enabled && tcp.tls.client_auth;
+    if loopback || signed || client_certs {
+        return Ok(());
+    }
+    Err(BotError::Config(format!(
+        "Refusing to accept bridge commands on {} without required signatures or TLS client certificates",
+        tcp.listen_addr
+    ))
+    .into())
+}
+
+/// Отправитель команд моста
+///
+/// Перед отправкой в команде указывается версия протокола,
+/// и команда подписывается (см. [`BridgeAuth`]). Отправка
+/// блокирует поток, поэтому из асинхронного кода её нужно
+/// выполнять через [`tokio::task::spawn_blocking`]
+pub struct BridgeSender {
+    transport: SenderTransport,
+    auth: Arc<BridgeAuth>,
+}
+
+enum SenderTransport {
+    Unix(CommandSender),
+    Tcp(TcpCommandSender),
+}
+
+impl BridgeSender {
+    /// Создание отправителя по конфигурации моста
+    pub fn from_config(config: &BridgeConfig, auth: Arc<BridgeAuth>) -> UResult<Self> {
+        let transport = match config.transport.kind {
+            TransportKind::Unix => {
+                let path = super::socket::peer_path(&config.socket);
+                SenderTransport::Unix(CommandSender::new(path.to_string_lossy().into_owned()))
+            }
+            TransportKind::Tcp => {
+                let tcp = &config.transport;
+                let tls = if tcp.tls.enabled {
+                    let server_name = match tcp.tls.server_name.as_str() {
+                        "" => tcp.peer_addr.rsplit_once(':').map_or(tcp.peer_addr.as_str(), |(host, _)| host),
+                        name => name,
+                    };
+                    let server_name = ServerName::try_from(server_name)
+                        .map_err(|_| BotError::Config(format!("Invalid TLS server name: {}", server_name)))?;
+                    Some((client_tls(&tcp.tls)?, server_name))
+                } else {
+                    None
+                };
+                SenderTransport::Tcp(TcpCommandSender::new(tcp.peer_addr.clone(), tls))
+            }
+        };
+        Ok(Self { transport, auth })
+    }
+
+    pub fn send(&self, mut cmd: Command) -> UResult {
+        match &self.transport {
+            SenderTransport::Unix(sender) => {
+                // Сокеты qcproto не поддерживают приветствие, команда уходит с версией,
+                // указанной при её создании, а собеседник принимает любую поддерживаемую
+                self.auth.sign(&mut cmd);
+                sender.send(cmd).map_err(|why| BotError::Bridge(why).into())
+            }
+            SenderTransport::Tcp(sender) => sender.send(cmd, &self.auth),
+        }
+    }
+}
+
+/// Чтение одной строки ограниченной длины
+///
+/// Возвращает `None`, если собеседник закрыл подключение
+fn read_frame<R: BufRead>(reader: &mut R) -> UResult<Option<String>> {
+    let mut line = String::new();
+    let read = reader.by_ref().take(MAX_FRAME_LEN + 1).read_line(&mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if read as u64 > MAX_FRAME_LEN {
+        return Err(BotError::Protocol(format!("Frame exceeds {} bytes", MAX_FRAME_LEN)).into());
+    }
+    Ok(Some(line))
+}
+
+fn write_frame<W: Write, T: Serialize>(writer: &mut W, value: &T) -> UResult {
+    let mut frame = serde_json::to_vec(value)?;
+    frame.push(b'\n');
+    writer.write_all(&frame)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Отправитель команд по TCP
+///
+/// Каждая команда отправляется отдельным подключением.
+/// Сначала стороны обмениваются приветствием и выбирают
+/// версию протокола (см. [`protocol::Hello`]), затем
+/// команда передаётся строкой JSON
+pub struct TcpCommandSender {
+    addr: String,
+    tls: Option<(Arc<ClientConfig>, ServerName)>,
+}
+
+impl TcpCommandSender {
+    pub fn new(addr: String, tls: Option<(Arc<ClientConfig>, ServerName)>) -> Self {
+        Self { addr, tls }
+    }
+
+    pub fn send(&self, cmd: Command, auth: &BridgeAuth) -> UResult {
+        let stream = self.connect().map_err(BotError::Bridge)?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        match &self.tls {
+            Some((config, server_name)) => {
+                let conn = ClientConnection::new(config.clone(), server_name.clone())
+                    .map_err(|why| BotError::Bridge(why.into()))?;
+                let mut stream = StreamOwned::new(conn, stream);
+                exchange(&mut stream, cmd, auth)?;
+                stream.conn.send_close_notify();
+                stream.flush()?;
+                Ok(())
+            }
+            None => exchange(stream, cmd, auth),
+        }
+    }
+
+    /// Подключение к первому доступному адресу собеседника
+    fn connect(&self) -> UResult<TcpStream> {
+        let mut last_error = None;
+        for addr in self.addr.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => return Ok(stream),
+                Err(why) => last_error = Some(why),
+            }
+        }
+        Err(match last_error {
+            Some(why) => why.into(),
+            None => format!("{} does not resolve to any address", self.addr).into(),
+        })
+    }
+}
+
+fn exchange<S: Read + Write>(stream: S, mut cmd: Command, auth: &BridgeAuth) -> UResult {
+    let mut reader = BufReader::new(stream);
+    write_frame(reader.get_mut(), &protocol::Hello::new()).map_err(BotError::Bridge)?;
+    let reply: protocol::HelloReply = match read_frame(&mut reader).map_err(BotError::Bridge)? {
+        Some(line) => serde_json::from_str(line.trim_end())
+            .map_err(|why| BotError::Protocol(format!("Malformed handshake reply: {}", why)))?,
+        None => return Err(BotError::Protocol("Bridge peer closed the connection during handshake".to_owned()).into()),
+    };
+    let version = reply.version.ok_or_else(|| {
+        BotError::Protocol(format!(
+            "Bridge peer supports protocol versions {:?}, we support {:?}",
+            reply.supported,
+            protocol::supported_versions()
+        ))
+    })?;
+
+    protocol::stamp(&mut cmd, version)?;
+    auth.sign(&mut cmd);
+    write_frame(reader.get_mut(), &cmd).map_err(|why| BotError::Bridge(why).into())
+}
+
+/// Сервер команд моста на TCP
+///
+/// Принимает команды в том же виде, в котором их отправляет
+/// [`TcpCommandSender`], и обрабатывает их так же, как
+/// [`UnixCommandServer`] (см. [`Connection`])
+pub struct TcpCommandServer {
+    listener: TcpListener,
+    connection: Connection,
+    tls: Option<Arc<ServerConfig>>,
+}
+
+impl TcpCommandServer {
+    pub fn bind(
+        addr: &str,
+        connection: Connection,
+        tls: Option<Arc<ServerConfig>>,
+    ) -> UResult<Self> {
+        let listener = TcpListener::bind(addr)
+            .map_err(|why| BotError::Config(format!("Could not listen on {}: {}", addr, why)))?;
+        Ok(Self {
+            listener,
+            connection,
+            tls,
+        })
+    }
+
+    pub fn local_addr(&self) -> UResult<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    pub fn listen(&self) -> UResult {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(why) => {
+                    warn!(self.connection.logger, "Could not accept a bridge connection"; "reason" => format!("{:?}", why));
+                    continue;
+                }
+            };
+            let connection = match stream.peer_addr() {
+                Ok(addr) => self.connection.for_peer(addr.to_string()),
+                Err(_) => self.connection.clone(),
+            };
+            let tls = self.tls.clone();
+            thread::spawn(move || {
+                if let Err(why) = connection.serve_tcp(stream, tls) {
+                    warn!(connection.logger, "Bridge connection failed"; "reason" => format!("{:?}", why));
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Сервер команд моста на сокете Unix
+///
+/// Сокет создаётся доступным только владельцу (см.
+/// [`super::socket::bind_restricted`]). Подключения от
+/// пользователей не из списка разрешённых закрываются,
+/// без списка проверка не выполняется
+pub struct UnixCommandServer {
+    listener: UnixListener,
+    connection: Connection,
+    allowed_uids: Option<Vec<u32>>,
+}
+
+impl UnixCommandServer {
+    pub fn bind(path: &Path, connection: Connection, allowed_uids: Option<Vec<u32>>) -> UResult<Self> {
+        super::socket::prepare_socket(path)?;
+        let listener = super::socket::bind_restricted(path)?;
+        Ok(Self {
+            listener,
+            connection,
+            allowed_uids,
+        })
+    }
+
+    pub fn listen(&self) -> UResult {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(why) => {
+                    warn!(self.connection.logger, "Could not accept a bridge connection"; "reason" => format!("{:?}", why));
+                    continue;
+                }
+            };
+            if let Some(allowed_uids) = &self.allowed_uids {
+                match super::socket::peer_uid(&stream) {
+                    Ok(uid) if allowed_uids.contains(&uid) => (),
+                    Ok(uid) => {
+                        warn!(self.connection.logger, "Rejected a bridge connection from an unauthorized user"; "uid" => uid);
+                        continue;
+                    }
+                    Err(why) => {
+                        warn!(self.connection.logger, "Could not read bridge peer credentials"; "reason" => format!("{:?}", why));
+                        continue;
+                    }
+                }
+            }
+            let connection = self.connection.clone();
+            thread::spawn(move || {
+                if let Err(why) = connection.serve_unix(stream) {
+                    warn!(connection.logger, "Bridge connection failed"; "reason" => format!("{:?}", why));
+                }
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Обработка подключения к серверу команд
+///
+/// Собеседник может начать с приветствия и выбора версии
+/// протокола (см. [`protocol::Hello`]), отправители qcproto
+/// передают команду сразу. На запросы (см.
+/// [`requests::BridgeRequest`]) ответ приходит в том же
+/// подключении. На команды ответ отправляется, только если
+/// команда отклонена: неизвестного вида или не обработана
+#[derive(Clone)]
+pub struct Connection {
+    dispatcher: Arc<dyn CommandDispatcher + Send + Sync>,
+    requests: Arc<dyn RequestHandler + Send + Sync>,
+    metrics: Arc<Metrics>,
+    logger: Logger,
+}
+
+impl Connection {
+    pub fn new(
+        dispatcher: Arc<dyn CommandDispatcher + Send + Sync>,
+        requests: Arc<dyn RequestHandler + Send + Sync>,
+        metrics: Arc<Metrics>,
+        logger: Logger,
+    ) -> Self {
+        Self {
+            dispatcher,
+            requests,
+            metrics,
+            logger,
+        }
+    }
+
+    fn for_peer(&self, peer: String) -> Self {
+        Self {
+            logger: self.logger.new(slog::o!("peer" => peer)),
+            ..self.clone()
+        }
+    }
+
+    fn serve_tcp(&self, stream: TcpStream, tls: Option<Arc<ServerConfig>>) -> UResult {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        match tls {
+            Some(config) => {
+                let conn = ServerConnection::new(config).map_err(|why| BotError::Bridge(why.into()))?;
+                self.read_commands(StreamOwned::new(conn, stream))
+            }
+            None => self.read_commands(stream),
+        }
+    }
+
+    fn serve_unix(&self, stream: UnixStream) -> UResult {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        self.read_commands(stream)
+    }
+
+    fn read_commands<S: Read + Write>(&self, stream: S) -> UResult {
+        let mut reader = BufReader::new(stream);
+        let mut first = true;
+        while let Some(line) = read_frame(&mut reader)? {
+            let value: Value = match serde_json::from_str(line.trim_end()) {
+                Ok(value) => value,
+                Err(why) => {
+                    warn!(self.logger, "Received a malformed bridge frame"; "reason" => why.to_string());
+                    self.reply(&mut reader, BridgeResponse::Error {
+                        message: "malformed frame".to_owned(),
+                    });
+                    continue;
+                }
+            };
+            if std::mem::take(&mut first) && value.get("versions").is_some() {
+                if !self.handshake(&mut reader, value)? {
+                    return Ok(());
+                }
+                continue;
+            }
+            if value.get("request").is_some() {
+                let response = match requests::parse_request(value) {
+                    Ok(request) => self.requests.handle_request(request),
+                    Err(response) => {
+                        warn!(self.logger, "Received an unsupported bridge request"; "response" => format!("{:?}", response));
+                        self.metrics.increment("bridge.unsupported_request");
+                        response
+                    }
+                };
+                write_frame(reader.get_mut(), &response)?;
+                continue;
+            }
+            if let Some(response) = self.handle_command(value) {
+                self.reply(&mut reader, response);
+            }
+        }
+        Ok(())
+    }
+
+    /// Обработка команды, возвращает ответ, если она отклонена
+    fn handle_command(&self, value: Value) -> Option<BridgeResponse> {
+        let kind = command_kind(&value);
+        let cmd: Command = match serde_json::from_value(value) {
+            Ok(cmd) if is_supported_command(&cmd) => cmd,
+            Ok(_) | Err(_) if !kind.is_empty() => {
+                warn!(self.logger, "Received an unsupported bridge command"; "command" => &kind);
+                self.metrics.increment("bridge.unsupported_command");
+                return Some(BridgeResponse::UnsupportedCommand { command: kind });
+            }
+            _ => {
+                warn!(self.logger, "Received a malformed bridge command");
+                return Some(BridgeResponse::Error {
+                    message: "malformed command".to_owned(),
+                });
+            }
+        };
+        match self.dispatcher.dispatch(cmd) {
+            Ok(()) => None,
+            Err(why) => {
+                warn!(self.logger, "Could not handle a bridge command"; "reason" => format!("{:?}", why));
+                Some(BridgeResponse::Error {
+                    message: BotError::from(why).to_string(),
+                })
+            }
+        }
+    }
+
+    /// Ответ на отклонённую команду
+    ///
+    /// Отправители qcproto закрывают подключение сразу после
+    /// отправки, поэтому ошибка записи не считается сбоем
+    fn reply<S: Read + Write>(&self, reader: &mut BufReader<S>, response: BridgeResponse) {
+        if let Err(why) = write_frame(reader.get_mut(), &response) {
+            debug!(self.logger, "Could not reply to the bridge peer"; "reason" => format!("{:?}", why));
+        }
+    }
+
+    /// Выбор версии протокола, возвращает `false` при отказе
+    fn handshake<S: Read + Write>(&self, reader: &mut BufReader<S>, hello: Value) -> UResult<bool> {
+        let hello: protocol::Hello = serde_json::from_value(hello)
+            .map_err(|why| BotError::Protocol(format!("Malformed handshake: {}", why)))?;
+        let reply = protocol::HelloReply::to(&hello);
+        write_frame(reader.get_mut(), &reply)?;
+        if reply.version.is_none() {
+            warn!(self.logger, "Rejected a bridge peer with unsupported protocol versions";
+                "offered" => format!("{:?}", hello.versions),
+                "supported" => format!("{:?}", reply.supported),
+            );
+            self.metrics.increment("bridge.protocol.rejected");
+            return Ok(false);
+        }
+        Ok(true)
+    }
+}
+
+/// Вид команды в том виде, в котором он передаётся
+///
+/// Позволяет назвать собеседнику вид команды, даже если
+/// qcproto его не знает. Пустая строка, если вида нет
+fn command_kind(value: &Value) -> String {
+    match value.get("kind") {
+        Some(Value::String(kind)) => kind.clone(),
+        Some(Value::Object(kind)) => kind.keys().next().cloned().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+/// Команды, которые обрабатывает бот
+fn is_supported_command(cmd: &Command) -> bool {
+    matches!(cmd.kind, CommandKind::ForwardMessage { .. })
+}
+
+/// Сервер команд моста
+pub enum BridgeServer {
+    Unix(UnixCommandServer),
+    Tcp(TcpCommandServer),
+}
+
+impl BridgeServer {
+    /// Адрес сервера TCP
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self {
+            Self::Unix(_) => None,
+            Self::Tcp(server) => server.local_addr().ok(),
+        }
+    }
+
+    pub fn listen(&self) -> UResult {
+        match self {
+            Self::Unix(server) => server.listen(),
+            Self::Tcp(server) => server.listen(),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use crate::prelude::*;
+use serenity::{http::StatusCode, model::prelude::*, prelude::*};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Имя вебхуков, которыми управляет бот
+pub const BRIDGE_WEBHOOK_NAME: &str = "QueensCorsar Bridge";
+
+/// Максимальная длина имени автора сообщения вебхука
+const WEBHOOK_USERNAME_LIMIT: usize = 80;
+
+/// Имя автора по умолчанию
+const DEFAULT_USERNAME: &str = "Telegram";
+
+/// Слова, которые Discord запрещает в именах авторов вебхуков
+const RESERVED_USERNAME_WORDS: &[&str] = &["discord", "clyde"];
+
+/// Имена, которые Discord не принимает целиком
+const RESERVED_USERNAMES: &[&str] = &["everyone", "here"];
+
+/// Кэш вебхуков моста по каналам
+#[derive(Default)]
+pub struct WebhookCache {
+    webhooks: RwLock<HashMap<ChannelId, Webhook>>,
+}
+
+impl WebhookCache {
+    pub fn get(&self, channel_id: ChannelId) -> Option<Webhook> {
+        self.webhooks
+            .read()
+            .ok()
+            .and_then(|webhooks| webhooks.get(&channel_id).cloned())
+    }
+
+    pub fn insert(&self, channel_id: ChannelId, webhook: Webhook) {
+        if let Ok(mut webhooks) = self.webhooks.write() {
+            webhooks.insert(channel_id, webhook);
+        }
+    }
+
+    pub fn invalidate(&self, channel_id: ChannelId) {
+        if let Ok(mut webhooks) = self.webhooks.write() {
+            webhooks.remove(&channel_id);
+        }
+    }
+
+    /// Признак того, что вебхук принадлежит мосту
+    pub fn is_managed(&self, webhook_id: WebhookId) -> bool {
+        self.webhooks
+            .read()
+            .map(|webhooks| webhooks.values().any(|w| w.id == webhook_id))
+            .unwrap_or_default()
+    }
+}
+
+pub struct WebhooksKey;
+impl TypeMapKey for WebhooksKey {
+    type Value = Arc<WebhookCache>;
+}
+
+/// Получение кэша вебхуков моста
+pub async fn webhook_cache(ctx: &Context) -> UResult<Arc<WebhookCache>> {
+    let data = ctx.data.read().await;
+    data.get::<WebhooksKey>()
+        .cloned()
+        .ok_or(BotError::DataNotFound("Bridge webhook cache").into())
+}
+
+fn is_forbidden(why: &SerenityError) -> bool {
+    matches!(why, SerenityError::Http(why) if why.status_code() == Some(StatusCode::FORBIDDEN))
+}
+
+/// Получение вебхука моста для канала
+///
+/// Ранее созданный ботом вебхук переиспользуется, иначе
+/// создаётся новый. Возвращает `None`, если у бота нет
+/// права управления вебхуками в канале
+pub async fn managed_webhook(ctx: &Context, channel_id: ChannelId) -> UResult<Option<Webhook>> {
+    let cache = webhook_cache(ctx).await?;
+    if let Some(webhook) = cache.get(channel_id) {
+        return Ok(Some(webhook));
+    }
+
+    let existing = match channel_id.webhooks(&ctx.http).await {
+        Ok(webhooks) => webhooks,
+        Err(why) if is_forbidden(&why) => return Ok(None),
+        Err(why) => return Err(why.into()),
+    };
+    let bot_id = super::identity::bot_identity(ctx).await?.user_id;
+    let existing = existing.into_iter().find(|w| {
+        w.name.as_deref() == Some(BRIDGE_WEBHOOK_NAME)
+            && w.token.is_some()
+            && w.user.as_ref().is_some_and(|u| u.id == bot_id)
+    });
+    let webhook = match existing {
+        Some(webhook) => webhook,
+        None => match channel_id.create_webhook(&ctx.http, BRIDGE_WEBHOOK_NAME).await {
+            Ok(webhook) => webhook,
+            Err(why) if is_forbidden(&why) => return Ok(None),
+            Err(why) => return Err(why.into()),
+        },
+    };
+    cache.insert(channel_id, webhook.clone());
+    Ok(Some(webhook))
+}
+
+/// Имя автора, которое Discord примет для вебхука
+///
+/// Управляющие символы удаляются, запрещённые слова
+/// заменяются звёздочками, имя обрезается до 80 символов.
+/// Пустые и зарезервированные имена дополняются
+/// названием мессенджера
+pub fn webhook_username(author: &str) -> String {
+    let mut chars: Vec<char> = author.chars().filter(|c| !c.is_control()).collect();
+    for word in RESERVED_USERNAME_WORDS {
+        let word: Vec<char> = word.chars().collect();
+        for start in 0..chars.len().saturating_sub(word.len() - 1) {
+            let window = &chars[start..start + word.len()];
+            if window.iter().zip(&word).all(|(c, w)| c.to_ascii_lowercase() == *w) {
+                chars[start..start + word.len()].fill('*');
+            }
+        }
+    }
+    let name: String = chars.into_iter().collect();
+    let name = name.trim();
+    let name = if name.is_empty() {
+        DEFAULT_USERNAME.to_owned()
+    } else if RESERVED_USERNAMES.contains(&name.to_lowercase().as_str()) {
+        format!("{} ({})", name, DEFAULT_USERNAME)
+    } else {
+        name.to_owned()
+    };
+    name.chars().take(WEBHOOK_USERNAME_LIMIT).collect()
+}
+
+/// Публикация сообщения от имени удалённого автора
+///
+/// Возвращает `false`, если вебхук недоступен или Discord
+/// отклонил сообщение, и его нужно опубликовать обычным
+/// способом
+pub async fn post_as(
+    ctx: &Context,
+    channel_id: ChannelId,
+    author: &str,
+    avatar_url: Option<&str>,
+    content: &str,
+) -> UResult<bool> {
+    let webhook = match managed_webhook(ctx, channel_id).await? {
+        Some(webhook) => webhook,
+        None => return Ok(false),
+    };
+    let username = webhook_username(author);
+
+    let result = webhook
+        .execute(&ctx.http, false, |w| {
+            w.username(username).content(content).allowed_mentions(|am| am.empty_parse());
+            if let Some(url) = avatar_url {
+                w.avatar_url(url);
+            }
+            w
+        })
+        .await;
+    match result {
+        Ok(_) => Ok(true),
+        Err(SerenityError::Http(why)) if why.status_code() == Some(StatusCode::NOT_FOUND) => {
+            // Вебхук удалили вручную, при следующей пересылке он будет создан заново
+            webhook_cache(ctx).await?.invalidate(channel_id);
+            Ok(false)
+        }
+        // Имя автора или вложения, которые Discord не принимает от вебхука
+        Err(SerenityError::Http(why)) if why.status_code() == Some(StatusCode::BAD_REQUEST) => Ok(false),
+        Err(why) => Err(why.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_usernames_are_accepted_by_discord() {
+        assert_eq!(webhook_username("Innri"), "Innri");
+        assert_eq!(webhook_username("  "), "Telegram");
+        assert_eq!(webhook_username("DiscordFan"), "*******Fan");
+        assert_eq!(webhook_username("not clyde\n"), "not *****");
+        assert_eq!(webhook_username("everyone"), "everyone (Telegram)");
+        assert_eq!(webhook_username(&"я".repeat(100)).chars().count(), 80);
+        assert_eq!(webhook_username("cl"), "cl");
+    }
+}
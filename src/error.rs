@@ -0,0 +1,239 @@
+use serenity::http::{HttpError, StatusCode};
+use serenity::prelude::SerenityError;
+use std::error::Error;
+use std::fmt::{self, Display};
+
+/// Ошибка в упакованном виде, используемая в `UResult`
+pub type BoxedError = Box<dyn Error + Send + Sync>;
+
+/// Перечисление стандартных возможных ошибок бота
+///
+/// Ошибки сгруппированы по источнику. Любую ошибку можно
+/// дополнить описанием контекста через [`ResultExt::context`],
+/// а для пользователя получить понятное сообщение через
+/// [`BotError::user_message`].
+#[derive(Debug)]
+pub enum BotError {
+    /// Ошибка обращения к Discord API
+    Discord(SerenityError),
+    /// Ошибка передачи команд через мост
+    Bridge(BoxedError),
+    /// Нарушение протокола обмена командами
+    Protocol(String),
+    /// Ошибка конфигурации бота
+    Config(String),
+    /// Ошибка хранилища данных
+    Storage(BoxedError),
+    /// Некорректные действия или ввод пользователя
+    UserInput(UserInputError),
+    /// Истечение времени ожидания
+    TimedOut,
+    /// Отсутствие ожидаемых данных в контексте бота
+    DataNotFound(&'static str),
+    /// Прочие внутренние ошибки
+    Internal(BoxedError),
+    /// Ошибка с описанием контекста, в котором она произошла
+    Context {
+        context: String,
+        source: Box<BotError>,
+    },
+}
+
+/// Ошибки, вызванные действиями пользователя
+#[derive(Debug)]
+pub enum UserInputError {
+    NotInGuild,
+    Cancelled,
+    SessionActive,
+    DirectMessagesClosed,
+    UnknownConfigKey(String),
+    InvalidArgument(String),
+}
+
+impl BotError {
+    /// Краткое машинное название категории ошибки для логов
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Discord(_) => "discord",
+            Self::Bridge(_) => "bridge",
+            Self::Protocol(_) => "protocol",
+            Self::Config(_) => "config",
+            Self::Storage(_) => "storage",
+            Self::UserInput(_) => "user_input",
+            Self::TimedOut => "timeout",
+            Self::DataNotFound(_) | Self::Internal(_) => "internal",
+            Self::Context { source, .. } => source.kind(),
+        }
+    }
+
+    /// Дополнение ошибки описанием контекста
+    pub fn with_context<C: Into<String>>(self, context: C) -> Self {
+        Self::Context {
+            context: context.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Описание внешнего слоя контекста, если он есть
+    pub fn context(&self) -> Option<&str> {
+        match self {
+            Self::Context { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Исходная ошибка без слоёв контекста
+    pub fn root(&self) -> &BotError {
+        match self {
+            Self::Context { source, .. } => source.root(),
+            other => other,
+        }
+    }
+
+    /// Признак того, что пользователь закрыл личные сообщения
+    pub fn is_dm_closed(&self) -> bool {
+        match self.root() {
+            Self::UserInput(UserInputError::DirectMessagesClosed) => true,
+            Self::Discord(SerenityError::Http(why)) => matches!(
+                why.as_ref(),
+                HttpError::UnsuccessfulRequest(response) if response.error.code == 50007
+            ),
+            _ => false,
+        }
+    }
+
+    /// Признак штатного завершения диалога с пользователем
+    ///
+    /// Истечение времени ожидания ответа и отмена диалога
+    /// пользователем не являются сбоями бота
+    pub fn ends_conversation(&self) -> bool {
+        matches!(
+            self.root(),
+            Self::TimedOut | Self::UserInput(UserInputError::Cancelled)
+        )
+    }
+
+    /// Сообщение об ошибке, понятное пользователю
+    pub fn user_message(&self) -> String {
+        match self.root() {
+            Self::Discord(SerenityError::Http(why)) if why.status_code() == Some(StatusCode::FORBIDDEN) => {
+                "Мне не хватает прав для этого действия. Возможно, у тебя закрыты личные сообщения?".to_owned()
+            }
+            Self::Discord(_) => "Не получилось связаться с Discord, попробуй чуть позже.".to_owned(),
+            Self::Bridge(_) => "Мост с Telegram сейчас недоступен, попробуй чуть позже.".to_owned(),
+            Self::Protocol(_) => "Бот в Telegram прислал что-то непонятное, сообщи об этом Иннри!".to_owned(),
+            Self::Config(_) => "Бот неправильно настроен, сообщи об этом администрации гильдии!".to_owned(),
+            Self::Storage(_) => "Не получилось сохранить данные, сообщи об этом Иннри!".to_owned(),
+            Self::UserInput(UserInputError::NotInGuild) => {
+                "Кажется, ты не состоишь в группе гильдии.".to_owned()
+            }
+            Self::UserInput(UserInputError::Cancelled) => {
+                "Хорошо, прерываю. Если захочешь продолжить - введи команду `!rules` в чате гильдии!".to_owned()
+            }
+            Self::UserInput(UserInputError::SessionActive) => {
+                "У тебя уже идёт регистрация - просто ответь на мои вопросы там, где мы начали!".to_owned()
+            }
+            Self::UserInput(UserInputError::DirectMessagesClosed) => {
+                "Не получается написать тебе в личные сообщения - открой их в настройках приватности группы!".to_owned()
+            }
+            Self::UserInput(UserInputError::UnknownConfigKey(key)) => {
+                format!("Параметра `{}` в конфигурации нет.", key)
+            }
+            Self::UserInput(UserInputError::InvalidArgument(why)) => why.clone(),
+            Self::TimedOut => {
+                "Я так и не дождался ответа. Если захочешь продолжить - введи команду `!rules` в чате гильдии!"
+                    .to_owned()
+            }
+            _ => "Ого! Что-то дало сбой... Пожалуйста, не забудь сообщить об этом случае Иннри!".to_owned(),
+        }
+    }
+}
+
+impl Display for UserInputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotInGuild => write!(f, "User is not a part of the current session's guild"),
+            Self::Cancelled => write!(f, "User cancelled the conversation"),
+            Self::SessionActive => write!(f, "User already has an active signup session"),
+            Self::DirectMessagesClosed => write!(f, "User does not accept direct messages"),
+            Self::UnknownConfigKey(key) => write!(f, "Unknown configuration key: {}", key),
+            Self::InvalidArgument(why) => write!(f, "Invalid argument: {}", why),
+        }
+    }
+}
+
+impl Display for BotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Discord(why) => write!(f, "Discord API error: {}", why),
+            Self::Bridge(why) => write!(f, "Bridge transport error: {}", why),
+            Self::Protocol(why) => write!(f, "Protocol error: {}", why),
+            Self::Config(why) => write!(f, "Configuration error: {}", why),
+            Self::Storage(why) => write!(f, "Storage error: {}", why),
+            Self::UserInput(why) => write!(f, "{}", why),
+            Self::TimedOut => write!(f, "Operation timed out"),
+            Self::DataNotFound(what) => write!(f, "Data not found: {}", what),
+            Self::Internal(why) => write!(f, "Internal error: {}", why),
+            Self::Context { context, source } => write!(f, "{}: {}", context, source),
+        }
+    }
+}
+
+impl Error for BotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Discord(why) => Some(why),
+            Self::Bridge(why) | Self::Storage(why) | Self::Internal(why) => Some(why.as_ref()),
+            Self::Context { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl slog::KV for BotError {
+    fn serialize(&self, _record: &slog::Record, serializer: &mut dyn slog::Serializer) -> slog::Result {
+        serializer.emit_str("error kind", self.kind())?;
+        serializer.emit_arguments("reason", &format_args!("{}", self))
+    }
+}
+
+impl From<SerenityError> for BotError {
+    fn from(why: SerenityError) -> Self {
+        Self::Discord(why)
+    }
+}
+
+impl From<UserInputError> for BotError {
+    fn from(why: UserInputError) -> Self {
+        Self::UserInput(why)
+    }
+}
+
+impl From<BoxedError> for BotError {
+    /// Восстановление типизированной ошибки из `UResult`
+    fn from(why: BoxedError) -> Self {
+        let why = match why.downcast::<BotError>() {
+            Ok(why) => return *why,
+            Err(why) => why,
+        };
+        match why.downcast::<SerenityError>() {
+            Ok(why) => Self::Discord(*why),
+            Err(why) => Self::Internal(why),
+        }
+    }
+}
+
+/// Расширение результатов для добавления контекста к ошибкам
+pub trait ResultExt<T> {
+    #[allow(clippy::result_large_err)]
+    fn context<C: Into<String>>(self, context: C) -> Result<T, BotError>;
+}
+
+impl<T, E> ResultExt<T> for Result<T, E>
+where
+    E: Into<BotError>,
+{
+    fn context<C: Into<String>>(self, context: C) -> Result<T, BotError> {
+        self.map_err(|why| Into::<BotError>::into(why).with_context(context))
+    }
+}
@@ -1,25 +1,128 @@
-use crate::{prelude::*, core::application::SendersKey};
+use crate::prelude::*;
 use serenity::{model::prelude::*, prelude::*};
-use slog::o;
-use crate::application::PipesKey;
+use slog::{o, Logger};
+use crate::core::application::PipesKey;
+use crate::core::reporter::{report_error, welcome_fallback};
+use serenity::model::application::interaction::Interaction;
 
 use serenity::async_trait;
+use std::sync::Arc;
 
-pub struct Handler;
+pub struct Handler {
+    logger: Logger,
+}
+
+impl Handler {
+    pub fn new(logger: Logger) -> Self {
+        Self { logger }
+    }
+
+    /// Получение логгера для обработчика события
+    ///
+    /// Если основной логгер недоступен в контексте бота,
+    /// используется логгер, переданный при создании обработчика
+    async fn logger(&self, ctx: &Context, submodule: &str) -> Logger {
+        match child_logger(ctx, submodule).await {
+            Ok(value) => value,
+            Err(_) => self.logger.new(o!("from" => submodule.to_owned())),
+        }
+    }
+
+    async fn forward_to_bridge(&self, ctx: &Context, msg: Message, logger: &Logger) -> UResult {
+        if !identity::bot_identity(ctx).await?.is_self(msg.author.id) {
+            info!(logger, "Message event fired"; "content" => msg.content.clone());
+        } else {
+            info!(logger, "Message event fired");
+            return Ok(());
+        }
+
+        let own = match msg.webhook_id {
+            Some(webhook_id) => webhooks::webhook_cache(ctx).await?.is_managed(webhook_id),
+            None => false,
+        };
+        let outgoing = bridge::Outgoing {
+            channel_id: msg.channel_id,
+            author_id: msg.author.id,
+            author_bot: msg.author.bot,
+            own,
+        };
+        let config = bot_config(ctx).await?;
+        let state = bridge::bridge_state(ctx).await?;
+        let limiter = ratelimit::bridge_limiter(ctx).await?;
+        match bridge::check_outgoing(&outgoing, &config.bridge, &state, &limiter) {
+            Ok(()) => (),
+            Err(bridge::Skipped::BotAuthor) => {
+                debug!(logger, "Ignoring a message from a bot author");
+                return Ok(());
+            }
+            Err(bridge::Skipped::RateLimited) => {
+                debug!(logger, "Message dropped by the bridge rate limiter");
+                metrics::metrics(ctx).await?.increment("bridge.rate_limited");
+                return Ok(());
+            }
+            Err(_) => return Ok(()),
+        }
+
+        let content = transform::safe_content(ctx, &msg);
+        let nickname = msg.author_nick(&ctx.http).await;
+        let cmd = bridge::outgoing_command(&state, &config.bridge, msg.id, &msg.author.name, nickname, &content);
+        bridge::send_to_telegram(ctx, cmd).await
+    }
+
+    async fn announce_voice(&self, ctx: &Context, old: Option<VoiceState>, new: VoiceState) -> UResult {
+        let config = bot_config(ctx).await?;
+        let gid = match new.guild_id {
+            Some(gid) if gid.0 == config.general.guild_id => gid,
+            _ => return Ok(()),
+        };
+        if new.member.as_ref().is_some_and(|member| member.user.bot) {
+            return Ok(());
+        }
+        let old_channel = old.and_then(|state| state.channel_id);
+        for activity in announcements::voice_activities(&config.bridge.announcements, old_channel, new.channel_id) {
+            announcements::announce(ctx, gid, new.user_id, activity).await?;
+        }
+        Ok(())
+    }
+
+    async fn announce_game(&self, ctx: &Context, presence: Presence) -> UResult {
+        let config = bot_config(ctx).await?;
+        let gid = match presence.guild_id {
+            Some(gid) if gid.0 == config.general.guild_id => gid,
+            _ => return Ok(()),
+        };
+        if !config.bridge.announcements.games || presence.user.bot == Some(true) {
+            return Ok(());
+        }
+        let state = announcements::announcement_state(ctx).await?;
+        if let Some(game) = state.game_started(presence.user.id, announcements::current_game(&presence)) {
+            announcements::announce(ctx, gid, presence.user.id, announcements::Activity::GameStarted(game)).await?;
+        }
+        Ok(())
+    }
+
+    async fn share_context(&self, ctx: &Context) -> UResult {
+        let data = ctx.data.read().await;
+        let pipe = data
+            .get::<PipesKey<Context>>()
+            .and_then(|pipes| pipes.get("cmdserver"))
+            .ok_or(BotError::DataNotFound("Command server pipe"))?;
+        pipe.send(ctx.clone())
+    }
+}
 
 /// Блок имплементации черт обработчика событий
 ///
 /// Данный блок позволяет имплементировать методы Serenity
-/// для перехвата и обработки программных событий Discord
+/// для перехвата и обработки программных событий Discord.
+/// Обработчики не паникуют: все ошибки передаются в
+/// `report_error`
 #[async_trait]
 impl EventHandler for Handler {
     async fn message(&self, ctx: Context, msg: Message) {
-        let logger = match child_logger(&ctx, "event::message").await {
-            Ok(value) => value,
-            Err(why) => panic!("Failed to retrieve the logger: {:#?}", why),
-        };
+        let logger = self.logger(&ctx, "event::message").await;
         let logger = logger.new(o!(
-            "guild id" => match msg.guild(ctx.cache) {
+            "guild id" => match msg.guild(&ctx.cache) {
                 Some(guild) => format!("{}", guild.id),
                 None => "None".to_owned()
             },
@@ -27,65 +130,36 @@ impl EventHandler for Handler {
             "unique execution id" => unique_nano(),
         ));
 
-        let bot_uid = 1034395163302297600;
-        if msg.author.id != bot_uid {
-            info!(logger, "Message event fired"; "content" => msg.content.clone());
-        } else {
-            info!(logger, "Message event fired");
-            return;
-        }
-
-        let source_chat_id = 1032942368015515708 as u64;
-        if msg.channel_id != source_chat_id {
-            return;
+        if let Err(why) = self.forward_to_bridge(&ctx, msg, &logger).await {
+            report_error(&ctx, &logger, "message", why.into()).await;
         }
-
-        let author_fmt = {
-            let res = msg.author_nick(&ctx.http).await;
-            if let Some(nickname) = res {
-                format!("{} ({})", nickname, msg.author.name)
-            } else {
-                msg.author.name
-            }
-        };
-        let cmd = Command {
-            kind: CommandKind::ForwardMessage {
-                from: ActorInfos { server: "discord_server_id".to_owned(), name: author_fmt },
-                to: ActorInfos { server: "telegram_server_id".to_owned(), name: Default::default() },
-                content: msg.content
-            },
-            sender_bot_family: BotFamily::Discord,
-            protocol_version: PROTOCOL_VERSION
-        };
-        
-        {
-            let data = ctx.data.write().await;
-            let senders = data.get::<SendersKey>().unwrap();
-            let sender = senders.get("tgsender").unwrap();
-            if let Err(why) = sender.send(cmd) {
-                error!(logger, "Could not send a command to the other process; reason: {:#?}", why);
-            }
-        }
-
     }
 
     /// Обработчик события полной готовности бота
     ///
     /// Данное событие вызывается как только бот установил
     /// соединение с серверами дискорда и готов к последующей
-    /// инициализации и работе
+    /// инициализации и работе. Здесь же запоминаются сведения
+    /// о пользователе бота (см. [`identity::BotIdentity`])
     async fn ready(&self, ctx: Context, data_about_bot: Ready) {
-        let logger = match child_logger(&ctx, "event::ready").await {
-            Ok(value) => value,
-            Err(why) => panic!("Failed to retrieve the logger: {:#?}", why),
-        };
+        let logger = self.logger(&ctx, "event::ready").await;
 
-        let data = ctx.data.write().await;
-        let pipes = data.get::<PipesKey<Context>>().unwrap();
-        let pipe = pipes.get("cmdserver").unwrap();
+        let bot = identity::BotIdentity::from_ready(&data_about_bot);
+        info!(logger, "Bot identity resolved";
+            "user id" => bot.user_id.0,
+            "user name" => &bot.user_name,
+            "application id" => bot.application_id.0,
+            "shard" => format!("{}/{}", bot.shard_id, bot.shard_count),
+        );
+        ctx.data
+            .write()
+            .await
+            .insert::<identity::BotIdentityKey>(Arc::new(bot));
 
-        if let Err(why) = pipe.send(ctx.clone()) {
-            error!(logger, "Could not send bot context via the provided pipe"; "reason" => format!("{:#?}", why));
+        if let Err(why) = self.share_context(&ctx).await {
+            let why = BotError::from(why).with_context("Sending bot context via the provided pipe");
+            report_error(&ctx, &logger, "ready", why).await;
+            return;
         }
 
         info!(
@@ -98,10 +172,7 @@ impl EventHandler for Handler {
     ///
     /// Уточнить условия при которых происходит вызов
     async fn resume(&self, ctx: Context, _arg2: ResumedEvent) {
-        let logger = match child_logger(&ctx, "event::resume").await {
-            Ok(value) => value,
-            Err(why) => panic!("Failed to retrieve the logger: {:#?}", why),
-        };
+        let logger = self.logger(&ctx, "event::resume").await;
 
         info!(logger, "Resume event fired");
     }
@@ -109,26 +180,105 @@ impl EventHandler for Handler {
     /// Выход пользователя из группы
     ///
     /// Событие при уходе пользователя из группы, будь то
-    /// кик или самостоятельный уход
+    /// кик или самостоятельный уход. Активная сессия
+    /// регистрации пользователя прерывается
     async fn guild_member_removal(
         &self,
         ctx: Context,
-        _guild_id: GuildId,
+        guild_id: GuildId,
         user: User,
-        member_data: Option<Member>,
+        _member_data: Option<Member>,
     ) {
-        // todo!()
+        let logger = self.logger(&ctx, "event::guild_member_removal").await;
+        let logger = logger.new(o!(
+            "guild id" => guild_id.0,
+            "member" => format!("({}, {})", user.name, user.id.0),
+        ));
+
+        let cancelled = match sessions::session_registry(&ctx).await {
+            Ok(registry) => registry.cancel(guild_id, user.id),
+            Err(why) => Err(why),
+        };
+        match cancelled {
+            Ok(true) => info!(logger, "Member left the guild, sign up session cancelled"),
+            Ok(false) => (),
+            Err(why) => report_error(&ctx, &logger, "guild_member_removal", why.into()).await,
+        }
+    }
+
+    /// Обработчик взаимодействий с компонентами сообщений
+    ///
+    /// Используется для кнопок и форм рассмотрения заявок
+    /// на вступление в гильдию
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        let logger = self.logger(&ctx, "event::interaction_create").await;
+
+        let result = match &interaction {
+            Interaction::MessageComponent(component) => {
+                approval::handle_component(&ctx, &logger, component).await
+            }
+            Interaction::ModalSubmit(modal) => approval::handle_modal(&ctx, &logger, modal).await,
+            _ => Ok(()),
+        };
+        if let Err(why) = result {
+            report_error(&ctx, &logger, "interaction_create", why.into()).await;
+        }
     }
 
     /// Вход пользователя на сервер
     ///
-    /// Событие при входе пользователя на сервер
+    /// Событие при входе пользователя на сервер. Если
+    /// пользователю невозможно написать в личные сообщения,
+    /// регистрация проходит в ветке канала знакомства, а если
+    /// он не настроен - к пользователю обращаются в
+    /// приветственном канале
     async fn guild_member_addition(&self, ctx: Context, new_member: Member) {
-        match start_signup_session(&ctx, &new_member.user, &new_member.guild_id).await {
-            Err(contents) => {
-                panic!("Something went wrong! Reason: {}", contents)
-            }
-            _ => (),
+        let logger = self.logger(&ctx, "event::guild_member_addition").await;
+        let logger = logger.new(o!(
+            "guild id" => new_member.guild_id.0,
+            "member" => format!("({}, {})", new_member.user.name, new_member.user.id.0),
+        ));
+
+        let why = match start_signup_session(&ctx, &new_member.user, &new_member.guild_id).await {
+            Ok(_) => return,
+            Err(why) => BotError::from(why),
         };
+        if why.is_dm_closed() {
+            info!(logger, "Member has direct messages closed, falling back to the welcome channel");
+            match welcome_fallback(&ctx, &new_member.user).await {
+                Ok(_) => return,
+                Err(fallback) => {
+                    let fallback = BotError::from(fallback).with_context("Greeting the member in the welcome channel");
+                    report_error(&ctx, &logger, "guild_member_addition", fallback).await;
+                }
+            }
+        }
+        report_error(&ctx, &logger, "guild_member_addition", why).await;
+    }
+
+    /// Изменение голосового состояния участника
+    ///
+    /// Вход в голосовые каналы и выход из них объявляются в
+    /// мосте, если канал указан в настройках объявлений
+    async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
+        let logger = self.logger(&ctx, "event::voice_state_update").await;
+        let logger = logger.new(o!("member" => new.user_id.0));
+
+        if let Err(why) = self.announce_voice(&ctx, old, new).await {
+            report_error(&ctx, &logger, "voice_state_update", why.into()).await;
+        }
+    }
+
+    /// Изменение статуса присутствия участника
+    ///
+    /// Запуск игры объявляется в мосте, если это включено
+    /// в настройках объявлений
+    async fn presence_update(&self, ctx: Context, new_data: Presence) {
+        let logger = self.logger(&ctx, "event::presence_update").await;
+        let logger = logger.new(o!("member" => new_data.user.id.0));
+
+        if let Err(why) = self.announce_game(&ctx, new_data).await {
+            report_error(&ctx, &logger, "presence_update", why.into()).await;
+        }
     }
 }
@@ -0,0 +1,13 @@
+//! Бот QueenCorsar для Discord
+//!
+//! Библиотека используется исполняемым файлом бота и
+//! вспомогательными программами из `src/bin`
+
+pub mod commands;
+pub mod config;
+pub mod core;
+pub mod error;
+pub mod handler;
+pub mod logger;
+pub mod prelude;
+pub mod utility;
@@ -1,5 +1,4 @@
 use crate::prelude::*;
-use chrono;
 use slog::{o, Drain, Logger};
 
 fn get_datetime_str() -> String {
@@ -1,21 +1,16 @@
 // #![allow(unused)]
 
-mod commands;
-mod core;
-mod handler;
-mod logger;
-mod prelude;
-mod utility;
-mod sender;
-
-use crate::prelude::*;
-use crate::core::application;
+use queens_corsar::prelude::*;
+use queens_corsar::core::application;
+use queens_corsar::{config, logger};
 
 #[tokio::main]
 async fn main() -> UResult {
     let logger = logger::configure_compact_root()?;
+    let config = config::load_config()?;
     let reqs = application::BootstrapRequirements {
-        logger
+        logger,
+        config
     };
 
     application::bootstrap_application(reqs).await?;
@@ -1 +0,0 @@
-use crate::prelude::*;
@@ -1,11 +1,10 @@
 use crate::prelude::*;
 use nanoid::nanoid;
-use serenity::{model::prelude::*, prelude::*};
+use serenity::{http::StatusCode, model::prelude::*, prelude::*};
 use slog::Logger;
-use std::{sync::atomic::AtomicUsize, time::Duration};
+use std::sync::atomic::AtomicUsize;
 
 use slog::o;
-use std::sync::Arc;
 
 static UNIQUE_COUNTER: AtomicUsize = AtomicUsize::new(1);
 const NANOID_LEN: usize = 16;
@@ -21,28 +20,6 @@ pub fn unique_nano() -> String {
     nanoid!(NANOID_LEN)
 }
 
-/// Запроса ввода от пользователя
-///
-/// Данная функция сначала представляет пользователю
-/// указанное сообщение, затем ожидает его ответа в
-/// течении 120 секунд и возвращает результат ввода.
-/// Если же пользователь так и не ответил, возвращается
-/// ошибка типа `BotError::TimedOut`
-pub async fn query_from_user(ctx: &Context, user: &User, msg: &str) -> UResult<String> {
-    let dm = user.create_dm_channel(&ctx.http).await?;
-
-    dm.send_message(&ctx.http, |m| m.content(msg)).await?;
-
-    let reply = user
-        .await_reply(&ctx.shard)
-        .timeout(Duration::new(60 * 2, 0))
-        .await
-        .map(|m| Arc::try_unwrap(m).unwrap())
-        .map(|m| m.content);
-
-    reply.ok_or(BotError::TimedOut.into())
-}
-
 /// Создание дочерней копии основного логгера
 pub async fn child_logger(ctx: &Context, submodule: &str) -> UResult<Logger> {
     let data = ctx.data.read().await;
@@ -55,13 +32,65 @@ pub async fn child_logger(ctx: &Context, submodule: &str) -> UResult<Logger> {
         .ok_or(BotError::DataNotFound("Root logger").into())
 }
 
+/// Результат проверки членства пользователя в группе
+#[derive(Debug)]
+pub enum Membership {
+    /// Пользователь состоит в группе
+    Present,
+    /// Пользователь не состоит в группе
+    NotMember,
+    /// Пользователь покинул группу во время активной сессии
+    LeftDuringSession,
+    /// Проверку не удалось выполнить из-за ошибки Discord API
+    ApiFailure(SerenityError),
+}
+
+impl Membership {
+    /// Уточнение результата для проверок внутри активной сессии
+    ///
+    /// Пользователь, который не найден в группе во время
+    /// сессии, считается покинувшим её
+    pub fn in_session(self) -> Self {
+        match self {
+            Self::NotMember => Self::LeftDuringSession,
+            other => other,
+        }
+    }
+}
+
 /// Проверка присутствия пользователя в указанной группе
-pub async fn user_is_in_guild(ctx: &Context, user: &User, gid: &GuildId) -> UResult<bool> {
-    let r = ctx
-        .http
-        .search_guild_members(gid.0, user.name.as_str(), Some(1))
-        .await?;
-    Ok(r.len() > 0)
+///
+/// Сначала участник ищется в кэше, а при его отсутствии
+/// запрашивается у Discord по идентификатору пользователя
+pub async fn check_membership(ctx: &Context, uid: UserId, gid: &GuildId) -> Membership {
+    if ctx.cache.member(*gid, uid).is_some() {
+        return Membership::Present;
+    }
+    match ctx.http.get_member(gid.0, uid.0).await {
+        Ok(_) => Membership::Present,
+        Err(SerenityError::Http(why)) if why.status_code() == Some(StatusCode::NOT_FOUND) => {
+            Membership::NotMember
+        }
+        Err(why) => Membership::ApiFailure(why),
+    }
+}
+
+/// Проверка прав администратора у участника группы
+///
+/// Администратором считается участник с правами администратора
+/// или управления сервером, либо обладатель одной из ролей,
+/// указанных в конфигурации (`admin.role_ids`)
+pub async fn member_is_admin(ctx: &Context, member: &Member) -> UResult<bool> {
+    if let Ok(permissions) = member.permissions(ctx) {
+        if permissions.administrator() || permissions.manage_guild() {
+            return Ok(true);
+        }
+    }
+    let config = bot_config(ctx).await?;
+    Ok(member
+        .roles
+        .iter()
+        .any(|role| config.admin.role_ids.contains(&role.0)))
 }
 
 /// Отправка личного сообщения пользователю